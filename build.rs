@@ -0,0 +1,4 @@
+// rebuild when a migration is added so sqlx::migrate! picks it up
+fn main() {
+    println!("cargo:rerun-if-changed=migrations");
+}
@@ -0,0 +1,4 @@
+-- sessions that were already running when the bot came online (or ended while it was offline)
+ALTER TABLE vc_sessions ADD COLUMN partial INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE days ADD COLUMN vc_partial_sessions INTEGER NOT NULL DEFAULT 0;
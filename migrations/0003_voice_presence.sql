@@ -0,0 +1,13 @@
+-- one row per stay of a member in a voice channel, left_at is NULL while they're still there
+CREATE TABLE IF NOT EXISTS voice_presence(
+    id INTEGER PRIMARY KEY,
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    joined_at INTEGER NOT NULL,
+    left_at INTEGER
+);
+
+CREATE INDEX IF NOT EXISTS voice_presence_user ON voice_presence(server_id, user_id, joined_at);
+
+CREATE INDEX IF NOT EXISTS voice_presence_open ON voice_presence(server_id, left_at);
@@ -0,0 +1,7 @@
+ALTER TABLE vc_sessions ADD COLUMN peak_members INTEGER NOT NULL DEFAULT 0;
+
+-- present_since is NULL while the member is out of the channel
+ALTER TABLE vc_session_members ADD COLUMN first_joined INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN last_left INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN present_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN seconds REAL NOT NULL DEFAULT 0;
@@ -0,0 +1,22 @@
+ALTER TABLE vc_sessions ADD COLUMN fully_deafened_since INTEGER;
+ALTER TABLE vc_sessions ADD COLUMN fully_deafened_seconds REAL NOT NULL DEFAULT 0;
+
+ALTER TABLE vc_session_members ADD COLUMN muted_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN muted_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE vc_session_members ADD COLUMN deafened_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN deafened_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE vc_session_members ADD COLUMN streaming_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN streaming_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE vc_session_members ADD COLUMN video_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN video_seconds REAL NOT NULL DEFAULT 0;
+
+-- summed over members, so two people streaming for an hour is two hours
+ALTER TABLE days ADD COLUMN vc_stream_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE days ADD COLUMN vc_video_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE days ADD COLUMN vc_deafened_seconds REAL NOT NULL DEFAULT 0;
+
+CREATE TABLE IF NOT EXISTS vc_settings(
+    server_id INTEGER PRIMARY KEY,
+    exclude_deafened INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id)
+);
@@ -0,0 +1,9 @@
+ALTER TABLE vc_settings ADD COLUMN ignore_bots INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE vc_settings ADD COLUMN ignore_afk_channel INTEGER NOT NULL DEFAULT 1;
+
+CREATE TABLE IF NOT EXISTS vc_ignored_channels(
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (server_id, channel_id)
+);
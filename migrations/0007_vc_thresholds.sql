@@ -0,0 +1,4 @@
+ALTER TABLE vc_settings ADD COLUMN min_session_seconds INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE vc_settings ADD COLUMN min_participants INTEGER NOT NULL DEFAULT 1;
+-- whether sessions too short/small to be logged still go towards days.vc_seconds_elapsed
+ALTER TABLE vc_settings ADD COLUMN count_short_sessions INTEGER NOT NULL DEFAULT 1;
@@ -0,0 +1,5 @@
+ALTER TABLE vc_settings ADD COLUMN live_session_messages INTEGER NOT NULL DEFAULT 0;
+
+-- the "VC session in progress" message that gets edited as the session goes on
+ALTER TABLE vc_sessions ADD COLUMN message_channel_id INTEGER;
+ALTER TABLE vc_sessions ADD COLUMN message_id INTEGER;
@@ -0,0 +1,8 @@
+CREATE TABLE IF NOT EXISTS vc_channel_days(
+    day TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    vc_seconds_elapsed REAL NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (day, server_id, channel_id)
+);
@@ -0,0 +1,8 @@
+ALTER TABLE vc_sessions ADD COLUMN stage INTEGER NOT NULL DEFAULT 0;
+ALTER TABLE vc_sessions ADD COLUMN topic TEXT;
+
+-- on stage = not suppressed in a stage channel, hand raised = requested to speak while suppressed
+ALTER TABLE vc_session_members ADD COLUMN speaking_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN speaking_seconds REAL NOT NULL DEFAULT 0;
+ALTER TABLE vc_session_members ADD COLUMN hand_raised_since INTEGER;
+ALTER TABLE vc_session_members ADD COLUMN hand_raised_seconds REAL NOT NULL DEFAULT 0;
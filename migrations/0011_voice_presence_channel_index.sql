@@ -0,0 +1,2 @@
+-- for finding who else was in a channel at the same time
+CREATE INDEX IF NOT EXISTS voice_presence_channel ON voice_presence(server_id, channel_id, joined_at);
@@ -0,0 +1,15 @@
+-- days are stored as chrono's num_days_from_ce so consecutive days are just +1,
+-- user_id 0 is the streak of the whole server
+CREATE TABLE IF NOT EXISTS vc_streaks(
+    server_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    current INTEGER NOT NULL DEFAULT 0,
+    current_start INTEGER NOT NULL DEFAULT 0,
+    best INTEGER NOT NULL DEFAULT 0,
+    best_start INTEGER NOT NULL DEFAULT 0,
+    last_day INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (server_id, user_id)
+);
+
+ALTER TABLE vc_settings ADD COLUMN announce_streak_records INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,39 @@
+-- Time two members spent in the same channel, user_a is always the smaller id
+CREATE TABLE IF NOT EXISTS vc_copresence(
+    server_id INTEGER NOT NULL,
+    user_a INTEGER NOT NULL,
+    user_b INTEGER NOT NULL,
+    seconds INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (server_id, user_a, user_b)
+);
+
+-- Stays that have already ended, overlaps between two open stays get added when one of them closes
+INSERT INTO
+    vc_copresence(server_id, user_a, user_b, seconds)
+SELECT
+    a.server_id,
+    MIN(a.user_id, b.user_id),
+    MAX(a.user_id, b.user_id),
+    SUM(
+        MIN(
+            COALESCE(a.left_at, b.left_at),
+            COALESCE(b.left_at, a.left_at)
+        ) - MAX(a.joined_at, b.joined_at)
+    )
+FROM
+    voice_presence a
+    JOIN voice_presence b ON b.server_id = a.server_id
+    AND b.channel_id = a.channel_id
+    AND b.user_id != a.user_id
+    AND a.id < b.id
+    AND (
+        a.left_at IS NOT NULL
+        OR b.left_at IS NOT NULL
+    )
+    AND b.joined_at < COALESCE(a.left_at, b.left_at)
+    AND a.joined_at < COALESCE(b.left_at, a.left_at)
+GROUP BY
+    a.server_id,
+    MIN(a.user_id, b.user_id),
+    MAX(a.user_id, b.user_id);
@@ -0,0 +1,21 @@
+-- Counted as messages come in, so the daily report doesn't have to page through history
+CREATE TABLE IF NOT EXISTS message_counts(
+    day TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    messages INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (day, server_id, channel_id, user_id)
+);
+
+CREATE TABLE IF NOT EXISTS word_counts(
+    day TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    word TEXT NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (day, server_id, channel_id, word)
+);
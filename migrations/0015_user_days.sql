@@ -0,0 +1,10 @@
+-- Message totals per member for each daily report, days has the server wide totals
+CREATE TABLE IF NOT EXISTS user_days(
+    day TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    user_id INTEGER NOT NULL,
+    username TEXT NOT NULL,
+    messages_sent INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (day, server_id, user_id)
+);
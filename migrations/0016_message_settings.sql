@@ -0,0 +1,6 @@
+CREATE TABLE IF NOT EXISTS message_settings(
+    server_id INTEGER PRIMARY KEY,
+    -- Report on every channel in the server instead of just the daily log channel
+    guild_wide INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id)
+);
@@ -0,0 +1,9 @@
+-- Channels or categories included in/excluded from message stats, a thread goes by its
+-- channel and a channel by its category unless they have a filter of their own
+CREATE TABLE IF NOT EXISTS message_channel_filters(
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    included INTEGER NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (server_id, channel_id)
+);
@@ -0,0 +1,3 @@
+ALTER TABLE servers ADD COLUMN weekly_digest_channel INTEGER;
+
+ALTER TABLE servers ADD COLUMN monthly_digest_channel INTEGER;
@@ -0,0 +1,5 @@
+-- IANA timezone name, guilds without one go by the host's local time
+ALTER TABLE servers ADD COLUMN timezone TEXT;
+
+-- Hour of the day (in the guild's timezone) reports get sent at
+ALTER TABLE servers ADD COLUMN report_hour INTEGER NOT NULL DEFAULT 5;
@@ -0,0 +1,12 @@
+-- Comma separated codes of the built-in stop word lists to use
+ALTER TABLE message_settings ADD COLUMN stop_word_languages TEXT NOT NULL DEFAULT 'en';
+
+-- Per guild word tweaks: 'stop' adds a stop word, 'allow' lets a built-in stop word through
+-- and 'blacklist' keeps a word out of reports entirely
+CREATE TABLE IF NOT EXISTS message_word_rules(
+    server_id INTEGER NOT NULL,
+    word TEXT NOT NULL,
+    rule TEXT NOT NULL,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (server_id, word)
+);
@@ -0,0 +1,10 @@
+-- Two and three word phrases, counted after stop words are taken out
+CREATE TABLE IF NOT EXISTS phrase_counts(
+    day TEXT NOT NULL,
+    server_id INTEGER NOT NULL,
+    channel_id INTEGER NOT NULL,
+    phrase TEXT NOT NULL,
+    uses INTEGER NOT NULL DEFAULT 0,
+    FOREIGN KEY (server_id) REFERENCES servers(server_id),
+    PRIMARY KEY (day, server_id, channel_id, phrase)
+);
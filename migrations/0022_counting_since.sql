@@ -0,0 +1,3 @@
+-- Unix timestamp of when we first started counting the guild's messages, days that started
+-- before it have to be scraped from history instead
+ALTER TABLE servers ADD COLUMN counting_since INTEGER;
@@ -0,0 +1,4 @@
+-- Last time the bot saw the session going, a session that ends while the bot is offline gets
+-- closed at this time instead of whenever the bot comes back. It covers the session's members
+-- too, since anyone still in the session at this point was there until then
+ALTER TABLE vc_sessions ADD COLUMN last_seen INTEGER;
@@ -0,0 +1,2 @@
+-- Last time the bot saw an open stay, stays that ended while the bot was offline get closed here
+ALTER TABLE voice_presence ADD COLUMN last_seen INTEGER;
@@ -0,0 +1,8 @@
+-- Day key of the last day a daily report went out for, and of the first day of the last period
+-- each digest went out for. Reports are sent once the report hour has been reached and these
+-- show the latest one hasn't been sent yet
+ALTER TABLE servers ADD COLUMN last_daily_report TEXT;
+
+ALTER TABLE servers ADD COLUMN last_weekly_digest TEXT;
+
+ALTER TABLE servers ADD COLUMN last_monthly_digest TEXT;
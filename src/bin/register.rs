@@ -1,5 +1,11 @@
 #[path = "../commands/mod.rs"]
 mod commands;
+#[path = "../utils.rs"]
+#[allow(dead_code)]
+mod utils;
+#[path = "../words.rs"]
+#[allow(dead_code)]
+mod words;
 
 use std::env;
 
@@ -28,6 +34,15 @@ impl EventHandler for Handler {
             commands::set_vc_log_channel::register(),
             commands::disable_daily_log::register(),
             commands::disable_vc_logs::register(),
+            commands::set_digest_channel::register(),
+            commands::set_timezone::register(),
+            commands::disable_digest::register(),
+            commands::vc_settings::register(),
+            commands::message_settings::register(),
+            commands::vc_leaderboard::register(),
+            commands::vc_stats::register(),
+            commands::vc_friends::register(),
+            commands::vc_graph::register(),
         ];
 
         if env::args().nth(1).unwrap_or("".to_string()) == "global" {
@@ -0,0 +1,80 @@
+#![allow(dead_code)]
+use serenity::all::CommandInteraction;
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let guild_id = guild.get() as i64;
+
+    let Some(ResolvedOption {
+        value: ResolvedValue::String(period),
+        ..
+    }) = command.data.options().first().cloned()
+    else {
+        return CreateInteractionResponseMessage::new().content("Please provide a period");
+    };
+
+    let update = match period {
+        "weekly" => {
+            sqlx::query!(
+                "UPDATE
+                    servers
+                SET
+                    weekly_digest_channel = NULL
+                WHERE
+                    server_id = ?;",
+                guild_id
+            )
+            .execute(pool)
+            .await
+        }
+        _ => {
+            sqlx::query!(
+                "UPDATE
+                    servers
+                SET
+                    monthly_digest_channel = NULL
+                WHERE
+                    server_id = ?;",
+                guild_id
+            )
+            .execute(pool)
+            .await
+        }
+    };
+
+    let content = match update {
+        Ok(_) => format!(
+            "Disabled the {} digest, re-enable it with /set-digest-channel",
+            period
+        ),
+        Err(_) => format!(
+            "Failed to disable the {} digest (db error, maybe try again?)",
+            period
+        ),
+    };
+
+    CreateInteractionResponseMessage::new().content(content)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("disable-digest")
+        .description("stop sending a weekly or monthly digest")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "period", "which digest")
+                .add_string_choice("Weekly", "weekly")
+                .add_string_choice("Monthly", "monthly")
+                .required(true),
+        )
+}
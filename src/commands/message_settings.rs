@@ -0,0 +1,420 @@
+#![allow(dead_code)]
+use serenity::all::{ChannelType, CommandInteraction};
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+use crate::words;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let guild_id = guild.get() as i64;
+
+    let setup = sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);
+
+        INSERT
+            OR IGNORE INTO message_settings(server_id)
+        VALUES
+            (?);",
+        guild_id,
+        guild_id
+    )
+    .execute(pool)
+    .await;
+
+    if setup.is_err() {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to update message settings (db error, maybe try again?)");
+    }
+
+    for option in command.data.options() {
+        let update = match option {
+            ResolvedOption {
+                name: "guild-wide",
+                value: ResolvedValue::Boolean(guild_wide),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        message_settings
+                    SET
+                        guild_wide = ?
+                    WHERE
+                        server_id = ?;",
+                    guild_wide,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: name @ ("include-channel" | "exclude-channel"),
+                value: ResolvedValue::Channel(channel),
+                ..
+            } => {
+                let channel_id = channel.id.get() as i64;
+                let included = name == "include-channel";
+
+                sqlx::query!(
+                    "INSERT INTO
+                        message_channel_filters(server_id, channel_id, included)
+                    VALUES
+                        (?, ?, ?) ON CONFLICT(server_id, channel_id) DO
+                    UPDATE
+                    SET
+                        included = excluded.included;",
+                    guild_id,
+                    channel_id,
+                    included
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "reset-channel",
+                value: ResolvedValue::Channel(channel),
+                ..
+            } => {
+                let channel_id = channel.id.get() as i64;
+
+                sqlx::query!(
+                    "DELETE FROM
+                        message_channel_filters
+                    WHERE
+                        server_id = ?
+                        AND channel_id = ?;",
+                    guild_id,
+                    channel_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "stop-word-languages",
+                value: ResolvedValue::String(languages),
+                ..
+            } => {
+                let languages: Vec<&str> = languages
+                    .split(',')
+                    .map(str::trim)
+                    .filter(|language| !language.is_empty())
+                    .collect();
+
+                if let Some(unknown) = languages
+                    .iter()
+                    .find(|language| words::language_name(language).is_none())
+                {
+                    return CreateInteractionResponseMessage::new().content(format!(
+                        "Unknown language `{unknown}`, pick from: {}",
+                        language_list()
+                    ));
+                }
+
+                let languages = languages.join(",");
+
+                sqlx::query!(
+                    "UPDATE
+                        message_settings
+                    SET
+                        stop_word_languages = ?
+                    WHERE
+                        server_id = ?;",
+                    languages,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: name @ ("add-stop-word" | "remove-stop-word" | "blacklist-word"),
+                value: ResolvedValue::String(word),
+                ..
+            } => {
+                let Some(word) = single_word(word, elides(pool, guild_id).await) else {
+                    return CreateInteractionResponseMessage::new()
+                        .content(format!("`{word}` isn't a single word"));
+                };
+
+                let rule = match name {
+                    "add-stop-word" => "stop",
+                    "remove-stop-word" => "allow",
+                    _ => "blacklist",
+                };
+
+                sqlx::query!(
+                    "INSERT INTO
+                        message_word_rules(server_id, word, rule)
+                    VALUES
+                        (?, ?, ?) ON CONFLICT(server_id, word) DO
+                    UPDATE
+                    SET
+                        rule = excluded.rule;",
+                    guild_id,
+                    word,
+                    rule
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "unblacklist-word",
+                value: ResolvedValue::String(word),
+                ..
+            } => {
+                let word = single_word(word, elides(pool, guild_id).await).unwrap_or_default();
+
+                sqlx::query!(
+                    "DELETE FROM
+                        message_word_rules
+                    WHERE
+                        server_id = ?
+                        AND word = ?
+                        AND rule = 'blacklist';",
+                    guild_id,
+                    word
+                )
+                .execute(pool)
+                .await
+            }
+            _ => continue,
+        };
+
+        if update.is_err() {
+            return CreateInteractionResponseMessage::new()
+                .content("Failed to update message settings (db error, maybe try again?)");
+        }
+    }
+
+    let settings = sqlx::query!(
+        "SELECT
+            guild_wide,
+            stop_word_languages
+        FROM
+            message_settings
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_one(pool)
+    .await;
+
+    let filters = sqlx::query!(
+        "SELECT
+            channel_id,
+            included
+        FROM
+            message_channel_filters
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_all(pool)
+    .await;
+
+    let word_rules = sqlx::query!(
+        "SELECT
+            word,
+            rule
+        FROM
+            message_word_rules
+        WHERE
+            server_id = ?
+        ORDER BY
+            word;",
+        guild_id
+    )
+    .fetch_all(pool)
+    .await;
+
+    let content = match (settings, filters, word_rules) {
+        (Ok(settings), Ok(filters), Ok(word_rules)) => {
+            let list = |included: i64| {
+                let channels: Vec<String> = filters
+                    .iter()
+                    .filter(|filter| filter.included == included)
+                    .map(|filter| format!("<#{}>", filter.channel_id))
+                    .collect();
+
+                if channels.is_empty() {
+                    "none".to_string()
+                } else {
+                    channels.join(", ")
+                }
+            };
+
+            let words_with = |rule: &str| {
+                let words: Vec<&str> = word_rules
+                    .iter()
+                    .filter(|word_rule| word_rule.rule == rule)
+                    .map(|word_rule| word_rule.word.as_str())
+                    .collect();
+
+                if words.is_empty() {
+                    "none".to_string()
+                } else {
+                    words.join(", ")
+                }
+            };
+
+            let languages = settings
+                .stop_word_languages
+                .split(',')
+                .filter_map(words::language_name)
+                .collect::<Vec<_>>()
+                .join(", ");
+
+            // Blacklisted words aren't listed since the whole point is not showing them
+            let blacklisted = word_rules
+                .iter()
+                .filter(|word_rule| word_rule.rule == "blacklist")
+                .count();
+
+            format!(
+                "**Message stats settings**\n\
+                Count every channel in the server: {}\n\
+                Included channels: {}\n\
+                Excluded channels: {}\n\
+                Stop word languages: {}\n\
+                Extra stop words: {}\n\
+                Allowed stop words: {}\n\
+                Blacklisted words: {}",
+                yes_no(settings.guild_wide != 0),
+                list(1),
+                list(0),
+                if languages.is_empty() {
+                    "none".to_string()
+                } else {
+                    languages
+                },
+                words_with("stop"),
+                words_with("allow"),
+                blacklisted
+            )
+        }
+        _ => "Failed to read message settings (db error, maybe try again?)".to_string(),
+    };
+
+    CreateInteractionResponseMessage::new().content(content)
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("message-settings")
+        .description("view or change what the daily message stats cover")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "guild-wide",
+            "count messages from every channel and thread, not just the daily log channel",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "include-channel",
+                "only count this channel or category (along with anything else included)",
+            )
+            .channel_types(channel_types()),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "exclude-channel",
+                "stop counting this channel or category",
+            )
+            .channel_types(channel_types()),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "reset-channel",
+                "remove the include/exclude setting for this channel or category",
+            )
+            .channel_types(channel_types()),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "stop-word-languages",
+            "built-in stop word lists to use, comma separated codes like en,es (en by default)",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "add-stop-word",
+            "leave a word out of most used words",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "remove-stop-word",
+            "let a stop word (built-in or added) show up in most used words again",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "blacklist-word",
+            "never show a word in any report",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "unblacklist-word",
+            "remove a word from the blacklist",
+        ))
+}
+
+// Words are stored the way the tokenizer would count them
+fn single_word(word: &str, elisions: bool) -> Option<String> {
+    match words::tokenize(word, elisions).as_slice() {
+        [word] => Some(word.clone()),
+        _ => None,
+    }
+}
+
+async fn elides(pool: &SqlitePool, guild_id: i64) -> bool {
+    sqlx::query!(
+        "SELECT
+            stop_word_languages
+        FROM
+            message_settings
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_one(pool)
+    .await
+    .is_ok_and(|settings| settings.stop_word_languages.split(',').any(words::elides))
+}
+
+fn language_list() -> String {
+    words::LANGUAGES
+        .iter()
+        .map(|(code, name, _)| format!("{code} ({name})"))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
+
+fn channel_types() -> Vec<ChannelType> {
+    vec![
+        ChannelType::Text,
+        ChannelType::News,
+        ChannelType::Category,
+        ChannelType::PublicThread,
+        ChannelType::PrivateThread,
+        ChannelType::NewsThread,
+    ]
+}
@@ -1,4 +1,13 @@
 pub mod disable_daily_log;
+pub mod disable_digest;
 pub mod disable_vc_logs;
+pub mod message_settings;
+pub mod set_digest_channel;
 pub mod set_msg_log_channel;
+pub mod set_timezone;
 pub mod set_vc_log_channel;
+pub mod vc_friends;
+pub mod vc_graph;
+pub mod vc_leaderboard;
+pub mod vc_settings;
+pub mod vc_stats;
@@ -0,0 +1,121 @@
+#![allow(dead_code)]
+use serenity::all::{ChannelType, CommandInteraction};
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let guild_id = guild.get() as i64;
+    let mut period = None;
+    let mut channel = None;
+
+    for option in command.data.options() {
+        match option {
+            ResolvedOption {
+                name: "period",
+                value: ResolvedValue::String(value),
+                ..
+            } => period = Some(value),
+            ResolvedOption {
+                name: "channel",
+                value: ResolvedValue::Channel(value),
+                ..
+            } => channel = Some(value),
+            _ => {}
+        }
+    }
+
+    let (Some(period), Some(channel)) = (period, channel) else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please provide a period and a text channel");
+    };
+
+    if channel.kind != ChannelType::Text {
+        return CreateInteractionResponseMessage::new().content("Please provide a text channel");
+    }
+
+    let channel_id = channel.id.get() as i64;
+
+    let sql_result = match period {
+        "weekly" => {
+            sqlx::query!(
+                "INSERT
+                    OR IGNORE INTO servers(server_id)
+                VALUES
+                    (?);
+
+                UPDATE
+                    servers
+                SET
+                    weekly_digest_channel = ?
+                WHERE
+                    server_id = ?;",
+                guild_id,
+                channel_id,
+                guild_id,
+            )
+            .execute(pool)
+            .await
+        }
+        _ => {
+            sqlx::query!(
+                "INSERT
+                    OR IGNORE INTO servers(server_id)
+                VALUES
+                    (?);
+
+                UPDATE
+                    servers
+                SET
+                    monthly_digest_channel = ?
+                WHERE
+                    server_id = ?;",
+                guild_id,
+                channel_id,
+                guild_id,
+            )
+            .execute(pool)
+            .await
+        }
+    };
+
+    let content = match sql_result {
+        Ok(_) => format!(
+            "<#{}> successfully set as {} digest channel",
+            channel_id, period
+        ),
+        Err(_) => format!("Failed to set given channel as {} digest channel", period),
+    };
+
+    CreateInteractionResponseMessage::new().content(content)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("set-digest-channel")
+        .description("set channel for weekly or monthly stats digests")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "period", "which digest")
+                .add_string_choice("Weekly", "weekly")
+                .add_string_choice("Monthly", "monthly")
+                .required(true),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "channel",
+                "channel to send the digest to",
+            )
+            .channel_types(vec![ChannelType::Text])
+            .required(true),
+        )
+}
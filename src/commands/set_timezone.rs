@@ -0,0 +1,146 @@
+#![allow(dead_code)]
+use chrono::Utc;
+use chrono_tz::Tz;
+use serenity::all::CommandInteraction;
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let guild_id = guild.get() as i64;
+
+    let setup = sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);",
+        guild_id
+    )
+    .execute(pool)
+    .await;
+
+    if setup.is_err() {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to set timezone (db error, maybe try again?)");
+    }
+
+    for option in command.data.options() {
+        let update = match option {
+            ResolvedOption {
+                name: "timezone",
+                value: ResolvedValue::String(timezone),
+                ..
+            } => {
+                let Ok(timezone) = timezone.parse::<Tz>() else {
+                    return CreateInteractionResponseMessage::new().content(format!(
+                        "Unknown timezone `{timezone}`, use a name like America/New_York or Europe/Berlin"
+                    ));
+                };
+
+                let timezone = timezone.name();
+
+                sqlx::query!(
+                    "UPDATE
+                        servers
+                    SET
+                        timezone = ?
+                    WHERE
+                        server_id = ?;",
+                    timezone,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "report-hour",
+                value: ResolvedValue::Integer(report_hour),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        servers
+                    SET
+                        report_hour = ?
+                    WHERE
+                        server_id = ?;",
+                    report_hour,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            _ => continue,
+        };
+
+        if update.is_err() {
+            return CreateInteractionResponseMessage::new()
+                .content("Failed to set timezone (db error, maybe try again?)");
+        }
+    }
+
+    let settings = sqlx::query!(
+        "SELECT
+            timezone,
+            report_hour
+        FROM
+            servers
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_one(pool)
+    .await;
+
+    let content = match settings {
+        Ok(settings) => {
+            let timezone = match settings.timezone.and_then(|tz| tz.parse::<Tz>().ok()) {
+                Some(tz) => format!(
+                    "{} (it's {} there)",
+                    tz.name(),
+                    Utc::now().with_timezone(&tz).format("%H:%M")
+                ),
+                None => "not set, using the bot's local time".to_string(),
+            };
+
+            format!(
+                "**Timezone settings**\n\
+                Timezone: {}\n\
+                Daily reports and digests are sent at {}:00",
+                timezone, settings.report_hour
+            )
+        }
+        Err(_) => "Failed to read timezone settings (db error, maybe try again?)".to_string(),
+    };
+
+    CreateInteractionResponseMessage::new().content(content)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("set-timezone")
+        .description("set the timezone days are counted in and when reports get sent")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::String,
+            "timezone",
+            "IANA timezone name, like America/New_York or Europe/Berlin",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "report-hour",
+                "hour of the day (0-23) to send the daily report and digests at (5 by default)",
+            )
+            .min_int_value(0)
+            .max_int_value(23),
+        )
+}
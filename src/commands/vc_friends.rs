@@ -0,0 +1,128 @@
+#![allow(dead_code)]
+use chrono::Utc;
+use serenity::all::CommandInteraction;
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+use crate::utils;
+
+const FRIENDS_SHOWN: i64 = 10;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let user = match command.data.options().first() {
+        Some(ResolvedOption {
+            value: ResolvedValue::User(user, _),
+            ..
+        }) => (*user).clone(),
+        _ => command.user.clone(),
+    };
+
+    let guild_id = guild.get() as i64;
+    let user_id = user.id.get() as i64;
+    let now = Utc::now().timestamp();
+
+    let friends = sqlx::query!(
+        r#"SELECT
+            user_id AS "user_id!: i64",
+            SUM(seconds) AS "seconds!: i64"
+        FROM
+            (
+                SELECT
+                    CASE
+                        WHEN user_a = ?2 THEN user_b
+                        ELSE user_a
+                    END AS user_id,
+                    seconds
+                FROM
+                    vc_copresence
+                WHERE
+                    server_id = ?1
+                    AND (
+                        user_a = ?2
+                        OR user_b = ?2
+                    )
+                UNION ALL
+                -- Overlap between stays that are still going isn't in vc_copresence yet
+                SELECT
+                    other.user_id,
+                    ?3 - MAX(me.joined_at, other.joined_at)
+                FROM
+                    voice_presence me
+                    JOIN voice_presence other ON other.server_id = me.server_id
+                    AND other.channel_id = me.channel_id
+                    AND other.user_id != me.user_id
+                    AND other.left_at IS NULL
+                WHERE
+                    me.server_id = ?1
+                    AND me.user_id = ?2
+                    AND me.left_at IS NULL
+            )
+        GROUP BY
+            user_id
+        HAVING
+            SUM(seconds) > 0
+        ORDER BY
+            2 DESC
+        LIMIT
+            ?4;"#,
+        guild_id,
+        user_id,
+        now,
+        FRIENDS_SHOWN
+    )
+    .fetch_all(pool)
+    .await;
+
+    let Ok(friends) = friends else {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to get vc friends (db error, maybe try again?)");
+    };
+
+    if friends.is_empty() {
+        return CreateInteractionResponseMessage::new()
+            .content(format!("<@{}> hasn't shared a vc with anyone yet", user.id));
+    }
+
+    let description = friends
+        .iter()
+        .enumerate()
+        .map(|(i, friend)| {
+            format!(
+                "**#{}** <@{}> - {}",
+                i + 1,
+                friend.user_id,
+                utils::format_from_seconds(friend.seconds as f32)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n");
+
+    let embed = CreateEmbed::new()
+        .title(format!("Who {} hangs out with most in vc", user.name))
+        .color(0xe190de)
+        .thumbnail(user.face())
+        .description(description);
+
+    CreateInteractionResponseMessage::new().embed(embed)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vc-friends")
+        .description("who a member spends the most time in vc with")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::User,
+            "user",
+            "member to look up (you by default)",
+        ))
+}
@@ -0,0 +1,204 @@
+#![allow(dead_code)]
+use std::collections::BTreeSet;
+
+use chrono::Utc;
+
+use serenity::all::{CommandInteraction, GuildId, UserId};
+use serenity::json::{self, json};
+use serenity::prelude::Context;
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{
+        CreateAttachment, CreateCommand, CreateCommandOption, CreateInteractionResponseMessage,
+    },
+};
+use sqlx::SqlitePool;
+
+struct Edge {
+    user_a: i64,
+    user_b: i64,
+    seconds: i64,
+}
+
+pub async fn run(
+    ctx: &Context,
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let format = match command.data.options().first() {
+        Some(ResolvedOption {
+            value: ResolvedValue::String(format),
+            ..
+        }) => *format,
+        _ => "json",
+    };
+
+    let guild_id = guild.get() as i64;
+    let now = Utc::now().timestamp();
+
+    let edges = sqlx::query_as!(
+        Edge,
+        r#"SELECT
+            user_a AS "user_a!: i64",
+            user_b AS "user_b!: i64",
+            SUM(seconds) AS "seconds!: i64"
+        FROM
+            (
+                SELECT
+                    user_a,
+                    user_b,
+                    seconds
+                FROM
+                    vc_copresence
+                WHERE
+                    server_id = ?1
+                UNION ALL
+                -- Overlap between stays that are still going isn't in vc_copresence yet
+                SELECT
+                    a.user_id,
+                    b.user_id,
+                    ?2 - MAX(a.joined_at, b.joined_at)
+                FROM
+                    voice_presence a
+                    JOIN voice_presence b ON b.server_id = a.server_id
+                    AND b.channel_id = a.channel_id
+                    AND b.user_id > a.user_id
+                    AND b.left_at IS NULL
+                WHERE
+                    a.server_id = ?1
+                    AND a.left_at IS NULL
+            )
+        GROUP BY
+            user_a,
+            user_b
+        HAVING
+            SUM(seconds) > 0
+        ORDER BY
+            user_a,
+            user_b;"#,
+        guild_id,
+        now
+    )
+    .fetch_all(pool)
+    .await;
+
+    let Ok(edges) = edges else {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to get vc co-presence (db error, maybe try again?)");
+    };
+
+    if edges.is_empty() {
+        return CreateInteractionResponseMessage::new()
+            .content("Nobody has shared a vc yet, nothing to export");
+    }
+
+    let users: BTreeSet<i64> = edges
+        .iter()
+        .flat_map(|edge| [edge.user_a, edge.user_b])
+        .collect();
+
+    let nodes: Vec<(i64, String)> = users
+        .into_iter()
+        .map(|user_id| (user_id, user_label(ctx, guild, user_id)))
+        .collect();
+
+    let attachment = if format == "graphml" {
+        CreateAttachment::bytes(graphml(&nodes, &edges), "vc-graph.graphml")
+    } else {
+        let graph = json!({
+            "nodes": nodes
+                .iter()
+                .map(|(id, label)| json!({ "id": id.to_string(), "label": label }))
+                .collect::<Vec<_>>(),
+            "edges": edges
+                .iter()
+                .map(|edge| json!({
+                    "source": edge.user_a.to_string(),
+                    "target": edge.user_b.to_string(),
+                    "seconds": edge.seconds,
+                }))
+                .collect::<Vec<_>>(),
+        });
+
+        let Ok(bytes) = json::to_vec_pretty(&graph) else {
+            return CreateInteractionResponseMessage::new()
+                .content("Failed to build the vc co-presence graph");
+        };
+
+        CreateAttachment::bytes(bytes, "vc-graph.json")
+    };
+
+    CreateInteractionResponseMessage::new()
+        .content(format!(
+            "VC co-presence graph: {} members, {} connections (weights are seconds spent together)",
+            nodes.len(),
+            edges.len()
+        ))
+        .add_file(attachment)
+}
+
+// Display name from the cache if we have them, the id otherwise
+fn user_label(ctx: &Context, guild: GuildId, user_id: i64) -> String {
+    let user = UserId::new(user_id as u64);
+
+    ctx.cache
+        .guild(guild)
+        .and_then(|guild| {
+            guild
+                .members
+                .get(&user)
+                .map(|member| member.display_name().to_string())
+        })
+        .or_else(|| ctx.cache.user(user).map(|user| user.name.clone()))
+        .unwrap_or_else(|| user_id.to_string())
+}
+
+fn graphml(nodes: &[(i64, String)], edges: &[Edge]) -> String {
+    let mut out = String::from(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+        <graphml xmlns=\"http://graphml.graphdrawing.org/xmlns\">\n  \
+        <key id=\"label\" for=\"node\" attr.name=\"label\" attr.type=\"string\"/>\n  \
+        <key id=\"seconds\" for=\"edge\" attr.name=\"seconds\" attr.type=\"long\"/>\n  \
+        <graph id=\"vc\" edgedefault=\"undirected\">\n",
+    );
+
+    for (id, label) in nodes {
+        out.push_str(&format!(
+            "    <node id=\"{id}\"><data key=\"label\">{}</data></node>\n",
+            escape_xml(label)
+        ));
+    }
+
+    for edge in edges {
+        out.push_str(&format!(
+            "    <edge source=\"{}\" target=\"{}\"><data key=\"seconds\">{}</data></edge>\n",
+            edge.user_a, edge.user_b, edge.seconds
+        ));
+    }
+
+    out.push_str("  </graph>\n</graphml>\n");
+    out
+}
+
+fn escape_xml(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&apos;")
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vc-graph")
+        .description("export who spends time with who in vc, for graph tools")
+        .add_option(
+            CreateCommandOption::new(CommandOptionType::String, "format", "file format")
+                .add_string_choice("JSON", "json")
+                .add_string_choice("GraphML", "graphml"),
+        )
+}
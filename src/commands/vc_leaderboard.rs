@@ -0,0 +1,226 @@
+#![allow(dead_code)]
+use chrono::{Datelike, Duration, Utc};
+use chrono_tz::Tz;
+use serenity::all::{ButtonStyle, CommandInteraction, ComponentInteraction, GuildId};
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{
+        CreateActionRow, CreateButton, CreateCommand, CreateCommandOption, CreateEmbed,
+        CreateEmbedFooter, CreateInteractionResponseMessage,
+    },
+};
+use sqlx::SqlitePool;
+
+use crate::utils;
+
+const PAGE_SIZE: i64 = 10;
+
+#[derive(Debug, Clone, Copy)]
+pub enum Period {
+    Today,
+    Week,
+    Month,
+    AllTime,
+}
+
+impl Period {
+    pub fn from_name(name: &str) -> Option<Self> {
+        match name {
+            "today" => Some(Self::Today),
+            "week" => Some(Self::Week),
+            "month" => Some(Self::Month),
+            "all-time" => Some(Self::AllTime),
+            _ => None,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Today => "today",
+            Self::Week => "week",
+            Self::Month => "month",
+            Self::AllTime => "all-time",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::Today => "today",
+            Self::Week => "this week",
+            Self::Month => "this month",
+            Self::AllTime => "all time",
+        }
+    }
+
+    // Unix timestamp the period starts at in the guild's timezone, weeks start on monday
+    pub fn start(&self, tz: Option<Tz>) -> i64 {
+        let today = utils::today(tz);
+
+        let first_day = match self {
+            Self::Today => today,
+            Self::Week => today - Duration::days(today.weekday().num_days_from_monday() as i64),
+            Self::Month => today.with_day(1).unwrap(),
+            Self::AllTime => return 0,
+        };
+
+        utils::midnight(first_day, tz).map_or(0, |midnight| midnight.timestamp())
+    }
+}
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let period = match command.data.options().first() {
+        Some(ResolvedOption {
+            value: ResolvedValue::String(period),
+            ..
+        }) => Period::from_name(period).unwrap_or(Period::Week),
+        _ => Period::Week,
+    };
+
+    leaderboard(pool, guild, period, 0).await
+}
+
+// Handles the previous/next buttons, their ids look like "vc-leaderboard:week:2"
+pub async fn run_page(
+    component: &ComponentInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = component.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let mut parts = component.data.custom_id.split(':').skip(1);
+    let period = parts
+        .next()
+        .and_then(Period::from_name)
+        .unwrap_or(Period::Week);
+    let page = parts.next().and_then(|page| page.parse().ok()).unwrap_or(0);
+
+    leaderboard(pool, guild, period, page).await
+}
+
+async fn leaderboard(
+    pool: &SqlitePool,
+    guild: GuildId,
+    period: Period,
+    page: i64,
+) -> CreateInteractionResponseMessage {
+    let guild_id = guild.get() as i64;
+    let start = period.start(utils::fetch_timezone(pool, guild).await);
+    let now = Utc::now().timestamp();
+    let offset = page * PAGE_SIZE;
+
+    // Stays are clipped to the period, and ones still going count up to now
+    let members = sqlx::query!(
+        r#"SELECT
+            user_id,
+            SUM(MIN(COALESCE(left_at, ?1), ?1) - MAX(joined_at, ?2)) AS "seconds!: i64"
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?3
+            AND COALESCE(left_at, ?1) > ?2
+        GROUP BY
+            user_id
+        ORDER BY
+            2 DESC
+        LIMIT
+            ?4 OFFSET ?5;"#,
+        now,
+        start,
+        guild_id,
+        PAGE_SIZE,
+        offset
+    )
+    .fetch_all(pool)
+    .await;
+
+    let member_count = sqlx::query!(
+        r#"SELECT
+            COUNT(DISTINCT user_id) AS "count!: i64"
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?1
+            AND COALESCE(left_at, ?2) > ?3;"#,
+        guild_id,
+        now,
+        start
+    )
+    .fetch_one(pool)
+    .await;
+
+    let (Ok(members), Ok(member_count)) = (members, member_count) else {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to get the vc leaderboard (db error, maybe try again?)");
+    };
+
+    let pages = ((member_count.count + PAGE_SIZE - 1) / PAGE_SIZE).max(1);
+
+    let description = if members.is_empty() {
+        "Nobody has been in vc yet".to_string()
+    } else {
+        members
+            .iter()
+            .enumerate()
+            .map(|(i, member)| {
+                format!(
+                    "#{} <@{}> - {}",
+                    offset + i as i64 + 1,
+                    member.user_id,
+                    utils::format_from_seconds(member.seconds as f32)
+                )
+            })
+            .collect::<Vec<_>>()
+            .join("\n")
+    };
+
+    let embed = CreateEmbed::new()
+        .title(format!("VC leaderboard ({})", period.label()))
+        .color(0xe190de)
+        .description(description)
+        .footer(CreateEmbedFooter::new(format!(
+            "Page {}/{}",
+            page + 1,
+            pages
+        )));
+
+    let buttons = CreateActionRow::Buttons(vec![
+        CreateButton::new(format!("vc-leaderboard:{}:{}", period.name(), page - 1))
+            .label("Previous")
+            .style(ButtonStyle::Secondary)
+            .disabled(page == 0),
+        CreateButton::new(format!("vc-leaderboard:{}:{}", period.name(), page + 1))
+            .label("Next")
+            .style(ButtonStyle::Secondary)
+            .disabled(page + 1 >= pages),
+    ]);
+
+    CreateInteractionResponseMessage::new()
+        .embed(embed)
+        .components(vec![buttons])
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vc-leaderboard")
+        .description("who has spent the most time in vc")
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::String,
+                "period",
+                "time period to rank by (this week by default)",
+            )
+            .add_string_choice("today", "today")
+            .add_string_choice("this week", "week")
+            .add_string_choice("this month", "month")
+            .add_string_choice("all time", "all-time"),
+        )
+}
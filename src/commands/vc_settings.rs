@@ -0,0 +1,379 @@
+#![allow(dead_code)]
+use serenity::all::{ChannelType, CommandInteraction};
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let guild_id = guild.get() as i64;
+
+    let setup = sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);
+
+        INSERT
+            OR IGNORE INTO vc_settings(server_id)
+        VALUES
+            (?);",
+        guild_id,
+        guild_id
+    )
+    .execute(pool)
+    .await;
+
+    if setup.is_err() {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to update vc settings (db error, maybe try again?)");
+    }
+
+    for option in command.data.options() {
+        let update = match option {
+            ResolvedOption {
+                name: "exclude-deafened",
+                value: ResolvedValue::Boolean(exclude_deafened),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        exclude_deafened = ?
+                    WHERE
+                        server_id = ?;",
+                    exclude_deafened,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "ignore-bots",
+                value: ResolvedValue::Boolean(ignore_bots),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        ignore_bots = ?
+                    WHERE
+                        server_id = ?;",
+                    ignore_bots,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "ignore-afk-channel",
+                value: ResolvedValue::Boolean(ignore_afk_channel),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        ignore_afk_channel = ?
+                    WHERE
+                        server_id = ?;",
+                    ignore_afk_channel,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "ignore-channel",
+                value: ResolvedValue::Channel(channel),
+                ..
+            } => {
+                let channel_id = channel.id.get() as i64;
+
+                sqlx::query!(
+                    "INSERT
+                        OR IGNORE INTO vc_ignored_channels(server_id, channel_id)
+                    VALUES
+                        (?, ?);",
+                    guild_id,
+                    channel_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "unignore-channel",
+                value: ResolvedValue::Channel(channel),
+                ..
+            } => {
+                let channel_id = channel.id.get() as i64;
+
+                sqlx::query!(
+                    "DELETE FROM
+                        vc_ignored_channels
+                    WHERE
+                        server_id = ?
+                        AND channel_id = ?;",
+                    guild_id,
+                    channel_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "min-session-minutes",
+                value: ResolvedValue::Number(minutes),
+                ..
+            } => {
+                let min_session_seconds = (minutes * 60.0) as i64;
+
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        min_session_seconds = ?
+                    WHERE
+                        server_id = ?;",
+                    min_session_seconds,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "min-participants",
+                value: ResolvedValue::Integer(min_participants),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        min_participants = ?
+                    WHERE
+                        server_id = ?;",
+                    min_participants,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "count-short-sessions",
+                value: ResolvedValue::Boolean(count_short_sessions),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        count_short_sessions = ?
+                    WHERE
+                        server_id = ?;",
+                    count_short_sessions,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "live-session-messages",
+                value: ResolvedValue::Boolean(live_session_messages),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        live_session_messages = ?
+                    WHERE
+                        server_id = ?;",
+                    live_session_messages,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            ResolvedOption {
+                name: "announce-streak-records",
+                value: ResolvedValue::Boolean(announce_streak_records),
+                ..
+            } => {
+                sqlx::query!(
+                    "UPDATE
+                        vc_settings
+                    SET
+                        announce_streak_records = ?
+                    WHERE
+                        server_id = ?;",
+                    announce_streak_records,
+                    guild_id
+                )
+                .execute(pool)
+                .await
+            }
+            _ => continue,
+        };
+
+        if update.is_err() {
+            return CreateInteractionResponseMessage::new()
+                .content("Failed to update vc settings (db error, maybe try again?)");
+        }
+    }
+
+    let settings = sqlx::query!(
+        "SELECT
+            exclude_deafened,
+            ignore_bots,
+            ignore_afk_channel,
+            min_session_seconds,
+            min_participants,
+            count_short_sessions,
+            live_session_messages,
+            announce_streak_records
+        FROM
+            vc_settings
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_one(pool)
+    .await;
+
+    let ignored_channels = sqlx::query!(
+        "SELECT
+            channel_id
+        FROM
+            vc_ignored_channels
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_all(pool)
+    .await;
+
+    let content = match (settings, ignored_channels) {
+        (Ok(settings), Ok(ignored_channels)) => {
+            let ignored_channels = if ignored_channels.is_empty() {
+                "none".to_string()
+            } else {
+                ignored_channels
+                    .iter()
+                    .map(|record| format!("<#{}>", record.channel_id))
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            };
+
+            format!(
+                "**VC settings**\n\
+                Exclude time where everyone is deafened: {}\n\
+                Ignore bots: {}\n\
+                Ignore AFK channel: {}\n\
+                Ignored channels: {}\n\
+                Minimum session length to log: {} minutes\n\
+                Minimum participants to log: {}\n\
+                Count unlogged sessions towards the daily total: {}\n\
+                Live session messages: {}\n\
+                Announce streak records: {}",
+                yes_no(settings.exclude_deafened != 0),
+                yes_no(settings.ignore_bots != 0),
+                yes_no(settings.ignore_afk_channel != 0),
+                ignored_channels,
+                settings.min_session_seconds as f64 / 60.0,
+                settings.min_participants,
+                yes_no(settings.count_short_sessions != 0),
+                yes_no(settings.live_session_messages != 0),
+                yes_no(settings.announce_streak_records != 0)
+            )
+        }
+        _ => "Failed to read vc settings (db error, maybe try again?)".to_string(),
+    };
+
+    CreateInteractionResponseMessage::new().content(content)
+}
+
+fn yes_no(value: bool) -> &'static str {
+    if value {
+        "yes"
+    } else {
+        "no"
+    }
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vc-settings")
+        .description("view or change how vc sessions are tracked")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "exclude-deafened",
+            "don't count time where everyone in the channel is deafened towards the daily total",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "ignore-bots",
+            "don't track bots in vc",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "ignore-afk-channel",
+            "don't track the server's afk channel (on by default)",
+        ))
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "ignore-channel",
+                "voice channel to stop tracking",
+            )
+            .channel_types(vec![ChannelType::Voice, ChannelType::Stage]),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Channel,
+                "unignore-channel",
+                "voice channel to start tracking again",
+            )
+            .channel_types(vec![ChannelType::Voice, ChannelType::Stage]),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Number,
+                "min-session-minutes",
+                "don't log sessions shorter than this",
+            )
+            .min_number_value(0.0),
+        )
+        .add_option(
+            CreateCommandOption::new(
+                CommandOptionType::Integer,
+                "min-participants",
+                "don't log sessions with fewer members than this",
+            )
+            .min_int_value(1),
+        )
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "count-short-sessions",
+            "whether sessions that weren't logged still count towards the daily total",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "live-session-messages",
+            "post a message when a session starts and keep it updated until it ends",
+        ))
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::Boolean,
+            "announce-streak-records",
+            "post in the vc log channel when someone beats their longest vc streak",
+        ))
+}
@@ -0,0 +1,230 @@
+#![allow(dead_code)]
+use chrono::Utc;
+use serenity::all::CommandInteraction;
+use serenity::{
+    all::{CommandOptionType, ResolvedOption, ResolvedValue},
+    builder::{CreateCommand, CreateCommandOption, CreateEmbed, CreateInteractionResponseMessage},
+};
+use sqlx::SqlitePool;
+
+use crate::utils;
+
+pub async fn run(
+    command: &CommandInteraction,
+    pool: &SqlitePool,
+) -> CreateInteractionResponseMessage {
+    let Some(guild) = command.guild_id else {
+        return CreateInteractionResponseMessage::new()
+            .content("Please run this command in a guild");
+    };
+
+    let user = match command.data.options().first() {
+        Some(ResolvedOption {
+            value: ResolvedValue::User(user, _),
+            ..
+        }) => (*user).clone(),
+        _ => command.user.clone(),
+    };
+
+    let guild_id = guild.get() as i64;
+    let user_id = user.id.get() as i64;
+    let now = Utc::now().timestamp();
+
+    let totals = sqlx::query!(
+        r#"SELECT
+            COUNT(*) AS "sessions!: i64",
+            SUM(COALESCE(left_at, ?1) - joined_at) AS "total_seconds: i64",
+            MAX(COALESCE(left_at, ?1) - joined_at) AS "longest_seconds: i64"
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?2
+            AND user_id = ?3;"#,
+        now,
+        guild_id,
+        user_id
+    )
+    .fetch_one(pool)
+    .await;
+
+    let favorite_channel = sqlx::query!(
+        r#"SELECT
+            channel_id,
+            SUM(COALESCE(left_at, ?1) - joined_at) AS "seconds!: i64"
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?2
+            AND user_id = ?3
+        GROUP BY
+            channel_id
+        ORDER BY
+            2 DESC
+        LIMIT
+            1;"#,
+        now,
+        guild_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let friends = sqlx::query!(
+        r#"SELECT
+            user_id AS "user_id!: i64",
+            SUM(seconds) AS "seconds!: i64"
+        FROM
+            (
+                SELECT
+                    CASE
+                        WHEN user_a = ?2 THEN user_b
+                        ELSE user_a
+                    END AS user_id,
+                    seconds
+                FROM
+                    vc_copresence
+                WHERE
+                    server_id = ?1
+                    AND (
+                        user_a = ?2
+                        OR user_b = ?2
+                    )
+                UNION ALL
+                -- Overlap between stays that are still going isn't in vc_copresence yet
+                SELECT
+                    other.user_id,
+                    ?3 - MAX(me.joined_at, other.joined_at)
+                FROM
+                    voice_presence me
+                    JOIN voice_presence other ON other.server_id = me.server_id
+                    AND other.channel_id = me.channel_id
+                    AND other.user_id != me.user_id
+                    AND other.left_at IS NULL
+                WHERE
+                    me.server_id = ?1
+                    AND me.user_id = ?2
+                    AND me.left_at IS NULL
+            )
+        GROUP BY
+            user_id
+        HAVING
+            SUM(seconds) > 0
+        ORDER BY
+            2 DESC
+        LIMIT
+            3;"#,
+        guild_id,
+        user_id,
+        now
+    )
+    .fetch_all(pool)
+    .await;
+
+    let streak = sqlx::query!(
+        "SELECT
+            current,
+            best,
+            last_day
+        FROM
+            vc_streaks
+        WHERE
+            server_id = ?
+            AND user_id = ?;",
+        guild_id,
+        user_id
+    )
+    .fetch_optional(pool)
+    .await;
+
+    let (Ok(totals), Ok(favorite_channel), Ok(friends), Ok(streak)) =
+        (totals, favorite_channel, friends, streak)
+    else {
+        return CreateInteractionResponseMessage::new()
+            .content("Failed to get vc stats (db error, maybe try again?)");
+    };
+
+    if totals.sessions == 0 {
+        return CreateInteractionResponseMessage::new()
+            .content(format!("<@{}> hasn't been in vc yet", user.id));
+    }
+
+    let total_seconds = totals.total_seconds.unwrap_or(0);
+
+    let mut embed = CreateEmbed::new()
+        .title(format!("VC stats for {}", user.name))
+        .color(0xe190de)
+        .thumbnail(user.face())
+        .field(
+            "Total time in vc",
+            utils::format_from_seconds(total_seconds as f32),
+            true,
+        )
+        .field("Sessions", totals.sessions.to_string(), true)
+        .field(
+            "Average session",
+            utils::format_from_seconds(total_seconds as f32 / totals.sessions as f32),
+            true,
+        )
+        .field(
+            "Longest session",
+            utils::format_from_seconds(totals.longest_seconds.unwrap_or(0) as f32),
+            true,
+        );
+
+    if let Some(channel) = favorite_channel {
+        embed = embed.field(
+            "Favorite channel",
+            format!(
+                "<#{}> - {}",
+                channel.channel_id,
+                utils::format_from_seconds(channel.seconds as f32)
+            ),
+            true,
+        );
+    }
+
+    if let Some(streak) = streak {
+        let today = utils::day_number(utils::today(utils::fetch_timezone(pool, guild).await));
+        let current = utils::current_streak(streak.current, streak.last_day, today);
+
+        embed = embed.field(
+            "VC streak",
+            format!(
+                "{} (best: {})",
+                utils::unit("day", current as f32),
+                utils::unit("day", streak.best as f32)
+            ),
+            true,
+        );
+    }
+
+    if !friends.is_empty() {
+        embed = embed.field(
+            "Spends the most time with",
+            friends
+                .iter()
+                .map(|friend| {
+                    format!(
+                        "<@{}> - {}",
+                        friend.user_id,
+                        utils::format_from_seconds(friend.seconds as f32)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
+
+    CreateInteractionResponseMessage::new().embed(embed)
+}
+
+pub fn register() -> CreateCommand {
+    CreateCommand::new("vc-stats")
+        .description("vc stats for a member")
+        .add_option(CreateCommandOption::new(
+            CommandOptionType::User,
+            "user",
+            "member to get stats for (you by default)",
+        ))
+}
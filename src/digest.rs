@@ -0,0 +1,412 @@
+use std::collections::HashMap;
+
+use chrono::{Datelike, Duration, NaiveDate, Utc};
+use serenity::all::{ChannelId, GuildId};
+use serenity::builder::{CreateEmbed, CreateMessage};
+use serenity::json;
+use serenity::prelude::Context;
+use sqlx::SqlitePool;
+
+use crate::messages::MessageSettings;
+use crate::utils;
+
+#[derive(Debug, Clone, Copy)]
+pub enum DigestPeriod {
+    Weekly,
+    Monthly,
+}
+
+impl DigestPeriod {
+    fn title(&self) -> &'static str {
+        match self {
+            Self::Weekly => "Weekly digest",
+            Self::Monthly => "Monthly digest",
+        }
+    }
+
+    fn noun(&self) -> &'static str {
+        match self {
+            Self::Weekly => "week",
+            Self::Monthly => "month",
+        }
+    }
+
+    // First and last day of the period that ended before `today`, weeks start on monday
+    fn last_period(&self, today: NaiveDate) -> (NaiveDate, NaiveDate) {
+        match self {
+            Self::Weekly => {
+                let end = today - Duration::days(today.weekday().num_days_from_monday() as i64 + 1);
+                (end - Duration::days(6), end)
+            }
+            Self::Monthly => {
+                let end = today.with_day(1).unwrap().pred_opt().unwrap();
+                (end.with_day(1).unwrap(), end)
+            }
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct DigestStats {
+    pub messages: i64,
+    pub unique_chatters: i64,
+    pub vc_seconds: f64,
+    // Most messages first
+    pub top_members: Vec<(String, i64)>,
+    pub words: Vec<(String, i64)>,
+}
+
+impl DigestStats {
+    // Adds up the counters between two days (inclusive), for the channels the reports cover.
+    // Message totals come from the counters rather than the saved day rows, since those are
+    // only written for guilds with a daily log channel
+    pub async fn fetch(
+        ctx: &Context,
+        db: &SqlitePool,
+        guild_id: GuildId,
+        log_channel: Option<ChannelId>,
+        (start, end): (NaiveDate, NaiveDate),
+    ) -> Self {
+        let server_id = guild_id.get() as i64;
+        let days: Vec<String> = start
+            .iter_days()
+            .take_while(|day| *day <= end)
+            .map(utils::day_key)
+            .collect();
+        let days = json::to_string(&days).unwrap_or_default();
+
+        let mut stats = Self::default();
+
+        if let Ok(totals) = sqlx::query!(
+            r#"SELECT
+                COALESCE(SUM(vc_seconds_elapsed), 0.0) AS "vc_seconds!: f64"
+            FROM
+                days
+            WHERE
+                server_id = ?1
+                AND day IN (
+                    SELECT
+                        value
+                    FROM
+                        json_each(?2)
+                );"#,
+            server_id,
+            days
+        )
+        .fetch_one(db)
+        .await
+        {
+            stats.vc_seconds = totals.vc_seconds;
+        }
+
+        let counts = sqlx::query!(
+            r#"SELECT
+                channel_id,
+                user_id,
+                MAX(username) AS "username!: String",
+                SUM(messages) AS "messages!: i64"
+            FROM
+                message_counts
+            WHERE
+                server_id = ?1
+                AND day IN (
+                    SELECT
+                        value
+                    FROM
+                        json_each(?2)
+                )
+            GROUP BY
+                channel_id,
+                user_id;"#,
+            server_id,
+            days
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default();
+
+        let words = sqlx::query!(
+            r#"SELECT
+                channel_id,
+                word,
+                SUM(uses) AS "uses!: i64"
+            FROM
+                word_counts
+            WHERE
+                server_id = ?1
+                AND day IN (
+                    SELECT
+                        value
+                    FROM
+                        json_each(?2)
+                )
+            GROUP BY
+                channel_id,
+                word;"#,
+            server_id,
+            days
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default();
+
+        let settings = MessageSettings::cached(ctx, db, guild_id)
+            .await
+            .unwrap_or_default();
+        let guild = ctx.cache.guild(guild_id);
+        let reports_on = |channel_id: i64| {
+            settings.reports_on(
+                guild.as_deref(),
+                log_channel,
+                ChannelId::new(channel_id as u64),
+            )
+        };
+
+        let mut members: HashMap<i64, (String, i64)> = HashMap::new();
+
+        for record in counts
+            .into_iter()
+            .filter(|record| reports_on(record.channel_id))
+        {
+            members
+                .entry(record.user_id)
+                .or_insert((record.username, 0))
+                .1 += record.messages;
+            stats.messages += record.messages;
+        }
+
+        stats.unique_chatters = members.len() as i64;
+        stats.top_members = members.into_values().collect();
+        stats
+            .top_members
+            .sort_by_key(|member| std::cmp::Reverse(member.1));
+
+        let mut word_counts = HashMap::new();
+
+        for record in words {
+            if reports_on(record.channel_id) && settings.shows_word(&record.word) {
+                *word_counts.entry(record.word).or_insert(0) += record.uses;
+            }
+        }
+
+        stats.words = word_counts.into_iter().collect();
+        stats.words.sort_by_key(|word| std::cmp::Reverse(word.1));
+
+        stats
+    }
+}
+
+// Sends digests for the week and month that last ended to guilds that have reached their
+// report hour and haven't had them yet
+pub async fn send_digests(ctx: &Context, db: &SqlitePool) {
+    let now = Utc::now();
+
+    let servers = sqlx::query!(
+        "SELECT
+            server_id,
+            daily_log_channel,
+            weekly_digest_channel,
+            monthly_digest_channel,
+            timezone,
+            report_hour,
+            last_weekly_digest,
+            last_monthly_digest
+        FROM
+            servers
+        WHERE
+            weekly_digest_channel IS NOT NULL
+            OR monthly_digest_channel IS NOT NULL;"
+    )
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+
+    for row in servers {
+        let tz = row.timezone.and_then(|timezone| timezone.parse().ok());
+
+        if utils::local_hour(now, tz) < row.report_hour as u32 {
+            continue;
+        }
+
+        let today = utils::local_date(now, tz);
+        let guild_id = GuildId::new(row.server_id as u64);
+        let log_channel = row
+            .daily_log_channel
+            .map(|channel_id| ChannelId::new(channel_id as u64));
+
+        let due = [
+            (
+                DigestPeriod::Weekly,
+                row.weekly_digest_channel,
+                row.last_weekly_digest,
+            ),
+            (
+                DigestPeriod::Monthly,
+                row.monthly_digest_channel,
+                row.last_monthly_digest,
+            ),
+        ];
+
+        for (period, channel, last_sent) in due {
+            let Some(channel) = channel else {
+                continue;
+            };
+
+            let current = period.last_period(today);
+            let key = utils::day_key(current.0);
+
+            if last_sent.as_ref() == Some(&key) {
+                continue;
+            }
+
+            // Marked before sending so a digest that fails isn't retried every check
+            let marked = match period {
+                DigestPeriod::Weekly => {
+                    sqlx::query!(
+                        "UPDATE
+                        servers
+                    SET
+                        last_weekly_digest = ?
+                    WHERE
+                        server_id = ?;",
+                        key,
+                        row.server_id
+                    )
+                    .execute(db)
+                    .await
+                }
+                DigestPeriod::Monthly => {
+                    sqlx::query!(
+                        "UPDATE
+                        servers
+                    SET
+                        last_monthly_digest = ?
+                    WHERE
+                        server_id = ?;",
+                        key,
+                        row.server_id
+                    )
+                    .execute(db)
+                    .await
+                }
+            };
+
+            if let Err(why) = marked {
+                println!("Failed to mark {} digest as sent: {why}", period.noun());
+                continue;
+            }
+
+            // Guilds that just set up digests start with the next period instead of catching up
+            if last_sent.is_none() {
+                continue;
+            }
+
+            let previous = period.last_period(current.0);
+            let stats = DigestStats::fetch(ctx, db, guild_id, log_channel, current).await;
+            let previous_stats = DigestStats::fetch(ctx, db, guild_id, log_channel, previous).await;
+
+            let embed = digest_embed(period, current, &stats, &previous_stats);
+
+            if let Err(why) = ChannelId::new(channel as u64)
+                .send_message(&ctx.http, CreateMessage::new().add_embed(embed))
+                .await
+            {
+                println!("Failed to send {} digest: {why}", period.noun());
+            }
+        }
+    }
+}
+
+fn digest_embed(
+    period: DigestPeriod,
+    (start, end): (NaiveDate, NaiveDate),
+    stats: &DigestStats,
+    previous: &DigestStats,
+) -> CreateEmbed {
+    let noun = period.noun();
+
+    let mut embed = CreateEmbed::new()
+        .title(period.title())
+        .color(0xe190de)
+        .description(format!(
+            "{} - {}",
+            start.format("%b %-d"),
+            end.format("%b %-d, %Y")
+        ))
+        .field(
+            "Messages",
+            format!(
+                "{}{}",
+                stats.messages,
+                compared(stats.messages as f64, previous.messages as f64, noun)
+            ),
+            true,
+        )
+        .field(
+            "Unique chatters",
+            format!(
+                "{}{}",
+                stats.unique_chatters,
+                compared(
+                    stats.unique_chatters as f64,
+                    previous.unique_chatters as f64,
+                    noun
+                )
+            ),
+            true,
+        )
+        .field(
+            "Time in vc",
+            format!(
+                "{}{}",
+                utils::format_from_seconds(stats.vc_seconds as f32),
+                compared(stats.vc_seconds, previous.vc_seconds, noun)
+            ),
+            false,
+        );
+
+    if !stats.top_members.is_empty() {
+        embed = embed.field(
+            "Top members",
+            stats
+                .top_members
+                .iter()
+                .take(5)
+                .enumerate()
+                .map(|(i, (username, messages))| {
+                    format!("#{} {} - {} messages", i + 1, username, messages)
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
+
+    if !stats.words.is_empty() {
+        embed = embed.field(
+            "Most used words",
+            stats
+                .words
+                .iter()
+                .take(8)
+                .map(|(word, _)| word.as_str())
+                .collect::<Vec<_>>()
+                .join(", "),
+            false,
+        );
+    }
+
+    embed
+}
+
+// Percent change from the period before, left out when there's nothing to compare to
+fn compared(current: f64, previous: f64, noun: &str) -> String {
+    if previous == 0.0 {
+        return String::new();
+    }
+
+    format!(
+        " ({:+.0}% from last {noun})",
+        (current - previous) / previous * 100.0
+    )
+}
@@ -1,38 +1,32 @@
 mod commands;
+mod digest;
+mod messages;
+mod presence;
 mod stats;
+mod streaks;
 mod utils;
+mod voice;
+mod words;
 
-use stats::send_message_stats;
+use messages::{MessageSettings, MessageSettingsCache};
+use voice::{SyncReason, VoiceChannelState, VoiceSettings};
 
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::env;
 use std::sync::atomic::{AtomicBool, Ordering};
 use std::sync::Arc;
-use std::time::Instant;
 
-use serenity::all::{ChannelId, Guild, GuildId, Interaction, UserId, VoiceState};
+use serenity::all::{CommandInteraction, Guild, GuildId, Interaction, Message, VoiceState};
 use serenity::async_trait;
 use serenity::builder::{
-    CreateEmbed, CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
+    CreateInteractionResponse, CreateInteractionResponseMessage, CreateMessage,
 };
+use serenity::model::event::ResumedEvent;
 use serenity::model::gateway::Ready;
 use serenity::prelude::*;
 use sqlx::{migrate::MigrateDatabase, Sqlite, SqlitePool};
 use tokio_cron_scheduler::{Job, JobScheduler};
 
-struct VoiceChannelState;
-
-impl TypeMapKey for VoiceChannelState {
-    type Value = HashMap<ChannelId, VoiceChannelData>;
-}
-
-#[derive(Debug, Clone)]
-struct VoiceChannelData {
-    guild: GuildId,
-    members: HashSet<UserId>,
-    start: Instant,
-}
-
 struct Handler {
     is_loop_running: AtomicBool,
     db: sqlx::Pool<Sqlite>,
@@ -52,42 +46,37 @@ impl EventHandler for Handler {
         let ctx = Arc::new(ctx);
         let db = self.db.clone();
         let sched = JobScheduler::new().await.unwrap();
+        let live_ctx = ctx.clone();
+        let live_db = db.clone();
+
+        sched
+            .add(
+                Job::new_async("0 * * * * *", move |_, _| {
+                    let ctx = live_ctx.clone();
+                    let db = live_db.clone();
+
+                    Box::pin(async move {
+                        voice::mark_sessions_seen(&ctx, &db).await;
+                        streaks::credit_present_members(&ctx, &db).await;
+                        voice::refresh_live_messages(&ctx).await;
+                    })
+                })
+                .unwrap(),
+            )
+            .await
+            .unwrap();
 
+        // Guilds get their reports once their own report hour is reached, which can be on the half
+        // or quarter hour, so check every 15 minutes
         sched
             .add(
-                Job::new_async("0 0 5 * * *", move |_, _| {
+                Job::new_async("0 */15 * * * *", move |_, _| {
                     let ctx = ctx.clone();
                     let db = db.clone();
 
                     Box::pin(async move {
-                        let timestamp = utils::get_timestamp();
-
-                        let channel_ids = sqlx::query!(
-                            "SELECT
-                                daily_log_channel,
-                                vc_seconds_elapsed
-                            FROM
-                                servers
-                                LEFT JOIN days ON servers.server_id = days.server_id
-                                AND days.day = ?
-                            WHERE
-                                daily_log_channel IS NOT NULL;",
-                            timestamp
-                        )
-                        .fetch_all(&db)
-                        .await
-                        .unwrap();
-
-                        for row in channel_ids {
-                            let channel_id = row.daily_log_channel.unwrap();
-
-                            let _ = send_message_stats(
-                                &ctx,
-                                ChannelId::new(channel_id.try_into().unwrap()),
-                                row.vc_seconds_elapsed,
-                            )
-                            .await;
-                        }
+                        stats::send_daily_reports(&ctx, &db).await;
+                        digest::send_digests(&ctx, &db).await;
                     })
                 })
                 .unwrap(),
@@ -99,7 +88,21 @@ impl EventHandler for Handler {
         self.is_loop_running.swap(true, Ordering::Relaxed);
     }
 
+    async fn resume(&self, ctx: Context, _: ResumedEvent) {
+        for guild_id in ctx.cache.guilds() {
+            self.resync_voice(&ctx, guild_id).await;
+        }
+    }
+
     async fn guild_create(&self, ctx: Context, guild: Guild, is_new: Option<bool>) {
+        // Resync as soon as the guild is cached, so voice updates arriving before the rest of the
+        // guilds load don't treat sessions restored from the db as ones we saw the whole of
+        self.resync_voice(&ctx, guild.id).await;
+
+        if let Err(why) = messages::start_counting(&self.db, guild.id).await {
+            println!("Failed to start counting messages: {why}");
+        }
+
         if is_new.unwrap_or(false) {
             let welcome_message = guild
                 .channels
@@ -120,190 +123,144 @@ impl EventHandler for Handler {
     }
 
     async fn interaction_create(&self, ctx: Context, interaction: Interaction) {
-        if let Interaction::Command(command) = interaction {
-            let can_manage_guild = command.member.as_ref().map_or(false, |member| {
-                member.permissions(&ctx).map_or(false, |p| p.manage_guild())
-            });
-
-            let data = match command.data.name.as_str() {
-                "set-daily-log-channel" if can_manage_guild => {
-                    commands::set_msg_log_channel::run(&command, &self.db).await
-                }
-                "set-vc-session-log-channel" if can_manage_guild => {
-                    commands::set_vc_log_channel::run(&command, &self.db).await
-                }
-                "disable-daily-message-logs" if can_manage_guild => {
-                    commands::disable_daily_log::run(&command, &self.db).await
+        match interaction {
+            Interaction::Command(command) => self.handle_command(&ctx, command).await,
+            Interaction::Component(component) => {
+                let data = match component.data.custom_id.split(':').next() {
+                    Some("vc-leaderboard") => {
+                        commands::vc_leaderboard::run_page(&component, &self.db).await
+                    }
+                    _ => return,
+                };
+
+                let builder = CreateInteractionResponse::UpdateMessage(data);
+
+                if let Err(why) = component.create_response(&ctx.http, builder).await {
+                    println!("Cannot respond to component interaction: {why}");
                 }
-                "disable-vc-session-logs" if can_manage_guild => {
-                    commands::disable_vc_logs::run(&command, &self.db).await
-                }
-                "set-daily-log-channel"
-                | "set-vc-session-log-channel"
-                | "disable-daily-message-logs"
-                | "disable-vc-session-logs" => CreateInteractionResponseMessage::new().content(
-                    "You need to have the [Manage Server] permission to execute this command",
-                ),
-                _ => CreateInteractionResponseMessage::new().content("Unimplemented?!"),
-            };
-
-            let builder = CreateInteractionResponse::Message(data);
-
-            if let Err(why) = command.create_response(&ctx.http, builder).await {
-                println!("Cannot respond to slash command: {why}");
             }
+            _ => {}
         }
     }
 
-    // TODO: If theres an ongoing vc when the bot starts, the bot will give out wrong data for that session
-    async fn voice_state_update(&self, ctx: Context, _old: Option<VoiceState>, new: VoiceState) {
-        let mut data = ctx.data.write().await;
-        let channels = data.get_mut::<VoiceChannelState>().unwrap();
-        let guild = ctx.cache.guild(new.guild_id.unwrap()).unwrap().clone();
-
-        for user_voice_state in guild.voice_states.values() {
-            let channel_id = user_voice_state.channel_id.unwrap();
-
-            channels
-                .entry(channel_id)
-                .or_insert(VoiceChannelData {
-                    guild: guild.id,
-                    members: HashSet::new(),
-                    start: Instant::now(),
-                })
-                .members
-                .insert(user_voice_state.user_id);
+    async fn message(&self, ctx: Context, new_message: Message) {
+        if let Err(why) = messages::record_message(&ctx, &self.db, &new_message).await {
+            println!("Failed to count message: {why}");
         }
+    }
 
-        let dead_channels: HashMap<ChannelId, VoiceChannelData> = channels
-            .iter()
-            .filter(|(_, vc_data)| vc_data.guild == guild.id)
-            .filter(|(&channel_id, _)| {
-                !guild
-                    .voice_states
-                    .values()
-                    .any(|voice_state| voice_state.channel_id.unwrap() == channel_id)
-            })
-            .map(|(channel_id, vc_data)| (*channel_id, vc_data.clone()))
-            .collect();
-
-        if dead_channels.is_empty() {
+    async fn voice_state_update(&self, ctx: Context, old: Option<VoiceState>, new: VoiceState) {
+        let guild_id = new.guild_id.unwrap();
+        let settings = VoiceSettings::fetch(&self.db, guild_id).await;
+        let Some(guild) = ctx.cache.guild(guild_id).map(|guild| guild.clone()) else {
             return;
+        };
+
+        let tracked_channel = settings.tracked_channel(&guild, &new);
+        let was_tracked = old
+            .as_ref()
+            .is_some_and(|old| settings.tracked_channel(&guild, old).is_some());
+
+        if tracked_channel.is_some() || was_tracked {
+            streaks::update_streaks(&ctx, &self.db, &settings, guild_id, new.user_id).await;
         }
 
-        let mut time_elapsed = 0.0;
-        let mut embeds: Vec<CreateEmbed> = Vec::new();
-
-        for (channel_id, vc_data) in dead_channels {
-            let embed = CreateEmbed::new()
-                .title("VC session ended")
-                .color(0xe190de)
-                .field("Channel", format!("<#{}>", channel_id), false)
-                .field(
-                    "Participants",
-                    vc_data
-                        .members
-                        .into_iter()
-                        .map(|user| format!("<@{}>", user))
-                        .collect::<Vec<_>>()
-                        .join(", "),
-                    false,
-                )
-                .field(
-                    "Time elapsed",
-                    utils::format_from_seconds(
-                        Instant::now().duration_since(vc_data.start).as_secs_f32(),
-                    ),
-                    false,
-                );
-
-            time_elapsed += Instant::now().duration_since(vc_data.start).as_secs_f32();
-            embeds.push(embed);
-            channels.remove(&channel_id);
+        let reason = SyncReason::VoiceUpdate(new.user_id, tracked_channel);
+
+        if let Err(why) =
+            presence::update_presence(&self.db, &settings, &guild, old.as_ref(), &new).await
+        {
+            println!("Failed to update voice presence: {why}");
         }
 
-        let timestamp = utils::get_timestamp();
-        let guild_id = guild.id.get() as i64;
-
-        let Some(vc_logs_channel) = sqlx::query!(
-            "SELECT
-                vc_logs_channel
-            FROM
-                servers
-            WHERE
-                server_id = ?;",
-            guild_id
-        )
-        .fetch_optional(&self.db)
-        .await
-        .map_or(None, |record| {
-            record.and_then(|record| record.vc_logs_channel)
-        }) else {
+        voice::sync_guild(&ctx, &self.db, &settings, guild_id, reason).await;
+    }
+}
+
+impl Handler {
+    async fn handle_command(&self, ctx: &Context, command: CommandInteraction) {
+        let can_manage_guild = command
+            .member
+            .as_ref()
+            .is_some_and(|member| member.permissions(ctx).is_ok_and(|p| p.manage_guild()));
+
+        let data = match command.data.name.as_str() {
+            "set-daily-log-channel" if can_manage_guild => {
+                commands::set_msg_log_channel::run(&command, &self.db).await
+            }
+            "set-vc-session-log-channel" if can_manage_guild => {
+                commands::set_vc_log_channel::run(&command, &self.db).await
+            }
+            "disable-daily-message-logs" if can_manage_guild => {
+                commands::disable_daily_log::run(&command, &self.db).await
+            }
+            "disable-vc-session-logs" if can_manage_guild => {
+                commands::disable_vc_logs::run(&command, &self.db).await
+            }
+            "set-timezone" if can_manage_guild => {
+                let data = commands::set_timezone::run(&command, &self.db).await;
+                self.forget_message_settings(ctx, &command).await;
+                data
+            }
+            "set-digest-channel" if can_manage_guild => {
+                commands::set_digest_channel::run(&command, &self.db).await
+            }
+            "disable-digest" if can_manage_guild => {
+                commands::disable_digest::run(&command, &self.db).await
+            }
+            "vc-settings" if can_manage_guild => {
+                commands::vc_settings::run(&command, &self.db).await
+            }
+            "message-settings" if can_manage_guild => {
+                let data = commands::message_settings::run(&command, &self.db).await;
+                self.forget_message_settings(ctx, &command).await;
+                data
+            }
+            "vc-graph" if can_manage_guild => {
+                commands::vc_graph::run(ctx, &command, &self.db).await
+            }
+            "set-daily-log-channel"
+            | "set-vc-session-log-channel"
+            | "disable-daily-message-logs"
+            | "disable-vc-session-logs"
+            | "set-timezone"
+            | "set-digest-channel"
+            | "disable-digest"
+            | "vc-settings"
+            | "message-settings"
+            | "vc-graph" => CreateInteractionResponseMessage::new()
+                .content("You need to have the [Manage Server] permission to execute this command"),
+            "vc-leaderboard" => commands::vc_leaderboard::run(&command, &self.db).await,
+            "vc-stats" => commands::vc_stats::run(&command, &self.db).await,
+            "vc-friends" => commands::vc_friends::run(&command, &self.db).await,
+            _ => CreateInteractionResponseMessage::new().content("Unimplemented?!"),
+        };
+
+        let builder = CreateInteractionResponse::Message(data);
+
+        if let Err(why) = command.create_response(&ctx.http, builder).await {
+            println!("Cannot respond to slash command: {why}");
+        }
+    }
+
+    async fn forget_message_settings(&self, ctx: &Context, command: &CommandInteraction) {
+        if let Some(guild_id) = command.guild_id {
+            MessageSettings::invalidate(ctx, guild_id).await;
+        }
+    }
+
+    // Catches voice tracking up with the cache when we might've missed events
+    async fn resync_voice(&self, ctx: &Context, guild_id: GuildId) {
+        let settings = VoiceSettings::fetch(&self.db, guild_id).await;
+
+        voice::sync_guild(ctx, &self.db, &settings, guild_id, SyncReason::Resync).await;
+
+        let Some(guild) = ctx.cache.guild(guild_id).map(|guild| guild.clone()) else {
             return;
         };
 
-        let update = sqlx::query!(
-            "INSERT
-                OR IGNORE INTO servers(server_id)
-            VALUES
-                (?);
-
-            INSERT
-                OR IGNORE INTO days(day, server_id)
-            VALUES
-                (?, ?);
-
-            UPDATE
-                days
-            SET
-                vc_seconds_elapsed = vc_seconds_elapsed + ?
-            WHERE
-                server_id = ?
-                AND day = ?;",
-            guild_id,
-            timestamp,
-            guild_id,
-            time_elapsed,
-            guild_id,
-            timestamp
-        )
-        .execute(&self.db)
-        .await;
-
-        let vc_seconds_elapsed = sqlx::query!(
-            "SELECT
-                vc_seconds_elapsed
-            FROM
-                days
-            WHERE
-                server_id = ?
-                AND day = ?;",
-            guild_id,
-            timestamp
-        )
-        .fetch_one(&self.db)
-        .await;
-
-        let operations = update.and(vc_seconds_elapsed);
-
-        if let Ok(record) = operations {
-            let vc_seconds_elapsed = record.vc_seconds_elapsed.unwrap();
-
-            for embed in embeds {
-                if let Err(why) = ChannelId::new(vc_logs_channel.try_into().unwrap())
-                    .send_message(
-                        &ctx.http,
-                        CreateMessage::new().add_embed(embed.field(
-                            "Total time in vc today",
-                            utils::format_from_seconds(vc_seconds_elapsed as f32),
-                            false,
-                        )),
-                    )
-                    .await
-                {
-                    println!("Failed to send vc session log: {why}");
-                }
-            }
+        if let Err(why) = presence::reconcile_presence(&self.db, &settings, &guild).await {
+            println!("Failed to reconcile voice presence: {why}");
         }
     }
 }
@@ -320,30 +277,15 @@ async fn main() {
 
     let db = SqlitePool::connect(&db_url).await.unwrap();
 
-    sqlx::query!(
-        "CREATE TABLE IF NOT EXISTS servers(
-            server_id INTEGER PRIMARY KEY,
-            daily_log_channel INTEGER,
-            vc_logs_channel INTEGER
-        );
-
-        CREATE TABLE IF NOT EXISTS days(
-            day TEXT NOT NULL,
-            server_id INTEGER NOT NULL,
-            messages_sent INTEGER,
-            unique_chatters INTEGER,
-            vc_seconds_elapsed REAL DEFAULT 0,
-            FOREIGN KEY (server_id) REFERENCES servers(server_id),
-            PRIMARY KEY (day, server_id)
-        );"
-    )
-    .execute(&db)
-    .await
-    .unwrap();
+    sqlx::migrate!().run(&db).await.unwrap();
+
+    let vc_sessions = voice::load_sessions(&db).await.unwrap();
 
     let token = env::var("BOT_TOKEN").expect("token");
-    let intents =
-        GatewayIntents::privileged() | GatewayIntents::GUILD_VOICE_STATES | GatewayIntents::GUILDS;
+    let intents = GatewayIntents::privileged()
+        | GatewayIntents::GUILD_VOICE_STATES
+        | GatewayIntents::GUILDS
+        | GatewayIntents::GUILD_MESSAGES;
 
     let mut client = Client::builder(token, intents)
         .event_handler(Handler {
@@ -355,7 +297,8 @@ async fn main() {
 
     {
         let mut data = client.data.write().await;
-        data.insert::<VoiceChannelState>(HashMap::new());
+        data.insert::<VoiceChannelState>(vc_sessions);
+        data.insert::<MessageSettingsCache>(HashMap::new());
     }
 
     if let Err(why) = client.start().await {
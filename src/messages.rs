@@ -0,0 +1,340 @@
+use std::collections::{HashMap, HashSet};
+use std::sync::Arc;
+
+use chrono::Utc;
+use chrono_tz::Tz;
+use serenity::all::{ChannelId, Guild, GuildId, Message};
+use serenity::prelude::*;
+use sqlx::SqlitePool;
+
+use crate::utils;
+use crate::words;
+
+#[derive(Debug)]
+pub struct MessageSettings {
+    pub guild_wide: bool,
+    pub included_channels: HashSet<ChannelId>,
+    pub excluded_channels: HashSet<ChannelId>,
+    // Built-in stop word lists plus the guild's own, minus any it allowed
+    pub stop_words: HashSet<String>,
+    pub blacklist: HashSet<String>,
+    // Whether one of the stop word languages elides articles, see words::elides
+    pub elisions: bool,
+    pub timezone: Option<Tz>,
+}
+
+// Settings are needed for every message, so they're kept around until a command changes them
+pub struct MessageSettingsCache;
+
+impl TypeMapKey for MessageSettingsCache {
+    type Value = HashMap<GuildId, Arc<MessageSettings>>;
+}
+
+impl Default for MessageSettings {
+    fn default() -> Self {
+        Self {
+            guild_wide: false,
+            included_channels: HashSet::new(),
+            excluded_channels: HashSet::new(),
+            stop_words: words::stop_words("en").map(str::to_string).collect(),
+            blacklist: HashSet::new(),
+            elisions: false,
+            timezone: None,
+        }
+    }
+}
+
+impl MessageSettings {
+    pub async fn fetch(db: &SqlitePool, guild_id: GuildId) -> sqlx::Result<Self> {
+        let timezone = utils::try_fetch_timezone(db, guild_id).await?;
+        let guild_id = guild_id.get() as i64;
+
+        let mut settings = sqlx::query!(
+            "SELECT
+                guild_wide,
+                stop_word_languages
+            FROM
+                message_settings
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_optional(db)
+        .await?
+        .map_or_else(Self::default, |record| Self {
+            guild_wide: record.guild_wide != 0,
+            stop_words: record
+                .stop_word_languages
+                .split(',')
+                .flat_map(|language| words::stop_words(language.trim()))
+                .map(str::to_string)
+                .collect(),
+            elisions: record
+                .stop_word_languages
+                .split(',')
+                .any(|language| words::elides(language.trim())),
+            ..Self::default()
+        });
+
+        settings.timezone = timezone;
+
+        let filters = sqlx::query!(
+            "SELECT
+                channel_id,
+                included
+            FROM
+                message_channel_filters
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        for filter in filters {
+            let channel_id = ChannelId::new(filter.channel_id as u64);
+
+            if filter.included != 0 {
+                settings.included_channels.insert(channel_id);
+            } else {
+                settings.excluded_channels.insert(channel_id);
+            }
+        }
+
+        let rules = sqlx::query!(
+            "SELECT
+                word,
+                rule
+            FROM
+                message_word_rules
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_all(db)
+        .await?;
+
+        for rule in rules {
+            match rule.rule.as_str() {
+                "stop" => {
+                    settings.stop_words.insert(rule.word);
+                }
+                "allow" => {
+                    settings.stop_words.remove(&rule.word);
+                }
+                "blacklist" => {
+                    settings.blacklist.insert(rule.word);
+                }
+                _ => {}
+            }
+        }
+
+        Ok(settings)
+    }
+
+    // Errors aren't cached, so a failed lookup doesn't leave a guild on the defaults
+    pub async fn cached(
+        ctx: &Context,
+        db: &SqlitePool,
+        guild_id: GuildId,
+    ) -> sqlx::Result<Arc<Self>> {
+        if let Some(settings) = ctx
+            .data
+            .read()
+            .await
+            .get::<MessageSettingsCache>()
+            .and_then(|cache| cache.get(&guild_id))
+        {
+            return Ok(settings.clone());
+        }
+
+        let settings = Arc::new(Self::fetch(db, guild_id).await?);
+
+        if let Some(cache) = ctx.data.write().await.get_mut::<MessageSettingsCache>() {
+            cache.insert(guild_id, settings.clone());
+        }
+
+        Ok(settings)
+    }
+
+    // Drops the cached settings so the next message picks up the changes
+    pub async fn invalidate(ctx: &Context, guild_id: GuildId) {
+        if let Some(cache) = ctx.data.write().await.get_mut::<MessageSettingsCache>() {
+            cache.remove(&guild_id);
+        }
+    }
+
+    // Whether a word can show up in reports
+    pub fn shows_word(&self, word: &str) -> bool {
+        !self.stop_words.contains(word) && !self.blacklist.contains(word)
+    }
+
+    // The closest filter going thread -> channel -> category decides, with nothing set
+    // everything counts unless something else has been explicitly included
+    pub fn counts_channel(&self, guild: Option<&Guild>, channel_id: ChannelId) -> bool {
+        let mut current = Some(channel_id);
+
+        while let Some(channel_id) = current {
+            if self.included_channels.contains(&channel_id) {
+                return true;
+            }
+
+            if self.excluded_channels.contains(&channel_id) {
+                return false;
+            }
+
+            current = guild.and_then(|guild| {
+                guild
+                    .channels
+                    .get(&channel_id)
+                    .or_else(|| guild.threads.iter().find(|thread| thread.id == channel_id))
+                    .and_then(|channel| channel.parent_id)
+            });
+        }
+
+        self.included_channels.is_empty()
+    }
+
+    // Whether a channel's messages go into reports, which only cover the daily log channel
+    // unless guild wide stats are on
+    pub fn reports_on(
+        &self,
+        guild: Option<&Guild>,
+        log_channel: Option<ChannelId>,
+        channel_id: ChannelId,
+    ) -> bool {
+        (self.guild_wide || log_channel.is_none_or(|log_channel| log_channel == channel_id))
+            && self.counts_channel(guild, channel_id)
+    }
+}
+
+// Marks the guild as having its messages counted from now on, if it wasn't already
+pub async fn start_counting(db: &SqlitePool, guild_id: GuildId) -> sqlx::Result<()> {
+    let guild_id = guild_id.get() as i64;
+    let now = Utc::now().timestamp();
+
+    sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);
+
+        UPDATE
+            servers
+        SET
+            counting_since = ?
+        WHERE
+            server_id = ?
+            AND counting_since IS NULL;",
+        guild_id,
+        now,
+        guild_id
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+}
+
+// Adds a new message to today's counters for its channel and author
+pub async fn record_message(ctx: &Context, db: &SqlitePool, message: &Message) -> sqlx::Result<()> {
+    let Some(guild_id) = message.guild_id else {
+        return Ok(());
+    };
+
+    let settings = MessageSettings::cached(ctx, db, guild_id).await?;
+    // Only borrow the cached guild for as long as it takes to walk the channel's parents
+    let counted = settings.counts_channel(ctx.cache.guild(guild_id).as_deref(), message.channel_id);
+
+    if !counted {
+        return Ok(());
+    }
+
+    let day = utils::get_timestamp(settings.timezone);
+    let guild_id = guild_id.get() as i64;
+    let channel_id = message.channel_id.get() as i64;
+    let user_id = message.author.id.get() as i64;
+
+    let tokens = words::tokenize(&message.content, settings.elisions);
+    let mut word_counts: HashMap<&str, i64> = HashMap::new();
+    let mut phrase_counts: HashMap<String, i64> = HashMap::new();
+
+    for word in &tokens {
+        *word_counts.entry(word).or_insert(0) += 1;
+    }
+
+    let kept: Vec<&str> = tokens
+        .iter()
+        .map(String::as_str)
+        .filter(|word| !settings.stop_words.contains(*word))
+        .collect();
+
+    for phrase in words::phrases(&kept) {
+        *phrase_counts.entry(phrase).or_insert(0) += 1;
+    }
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);
+
+        INSERT INTO
+            message_counts(day, server_id, channel_id, user_id, username, messages)
+        VALUES
+            (?, ?, ?, ?, ?, 1) ON CONFLICT(day, server_id, channel_id, user_id) DO
+        UPDATE
+        SET
+            username = excluded.username,
+            messages = messages + 1;",
+        guild_id,
+        day,
+        guild_id,
+        channel_id,
+        user_id,
+        message.author.name
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for (word, uses) in word_counts {
+        sqlx::query!(
+            "INSERT INTO
+                word_counts(day, server_id, channel_id, word, uses)
+            VALUES
+                (?, ?, ?, ?, ?) ON CONFLICT(day, server_id, channel_id, word) DO
+            UPDATE
+            SET
+                uses = uses + excluded.uses;",
+            day,
+            guild_id,
+            channel_id,
+            word,
+            uses
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    for (phrase, uses) in phrase_counts {
+        sqlx::query!(
+            "INSERT INTO
+                phrase_counts(day, server_id, channel_id, phrase, uses)
+            VALUES
+                (?, ?, ?, ?, ?) ON CONFLICT(day, server_id, channel_id, phrase) DO
+            UPDATE
+            SET
+                uses = uses + excluded.uses;",
+            day,
+            guild_id,
+            channel_id,
+            phrase,
+            uses
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
@@ -0,0 +1,202 @@
+use std::collections::HashSet;
+
+use chrono::Utc;
+use serenity::all::{ChannelId, Guild, UserId, VoiceState};
+use sqlx::{Sqlite, SqlitePool, Transaction};
+
+use crate::voice::VoiceSettings;
+
+// Records a member's join/leave in voice_presence, switching channels counts as both.
+// Channels and members the guild doesn't track count as not being in voice at all
+pub async fn update_presence(
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    guild: &Guild,
+    old: Option<&VoiceState>,
+    new: &VoiceState,
+) -> sqlx::Result<()> {
+    let channel_id = settings.tracked_channel(guild, new);
+
+    // Mute/deafen/etc. updates don't move anyone
+    if old.is_some_and(|old| settings.tracked_channel(guild, old) == channel_id) {
+        return Ok(());
+    }
+
+    let now = Utc::now().timestamp();
+    let guild_id = guild.id.get() as i64;
+    let user_id = new.user_id.get() as i64;
+    let channel_id = channel_id.map(|channel_id| channel_id.get() as i64);
+
+    let mut tx = db.begin().await?;
+
+    let closing = sqlx::query!(
+        r#"SELECT
+            id AS "id!"
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?
+            AND user_id = ?
+            AND left_at IS NULL
+            AND channel_id IS NOT ?;"#,
+        guild_id,
+        user_id,
+        channel_id
+    )
+    .fetch_all(&mut *tx)
+    .await?;
+
+    for row in closing {
+        close_presence(&mut tx, row.id, now).await?;
+    }
+
+    if let Some(channel_id) = channel_id {
+        sqlx::query!(
+            "INSERT INTO
+                voice_presence(server_id, channel_id, user_id, joined_at, last_seen)
+            SELECT
+                ?1, ?2, ?3, ?4, ?4
+            WHERE
+                NOT EXISTS (
+                    SELECT
+                        1
+                    FROM
+                        voice_presence
+                    WHERE
+                        server_id = ?1
+                        AND channel_id = ?2
+                        AND user_id = ?3
+                        AND left_at IS NULL
+                );",
+            guild_id,
+            channel_id,
+            user_id,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+// Brings open presence rows in line with the guild cache after we may have missed events,
+// anyone who left while the bot was offline is counted as leaving when we last saw them
+pub async fn reconcile_presence(
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    guild: &Guild,
+) -> sqlx::Result<()> {
+    let now = Utc::now().timestamp();
+    let guild_id = guild.id.get() as i64;
+
+    let in_voice: HashSet<(UserId, ChannelId)> = guild
+        .voice_states
+        .values()
+        .filter_map(|voice_state| {
+            Some((
+                voice_state.user_id,
+                settings.tracked_channel(guild, voice_state)?,
+            ))
+        })
+        .collect();
+
+    let open = sqlx::query!(
+        r#"SELECT
+            id AS "id!",
+            channel_id,
+            user_id,
+            last_seen
+        FROM
+            voice_presence
+        WHERE
+            server_id = ?
+            AND left_at IS NULL;"#,
+        guild_id
+    )
+    .fetch_all(db)
+    .await?;
+
+    let mut tx = db.begin().await?;
+    let mut still_open: HashSet<(UserId, ChannelId)> = HashSet::new();
+
+    for row in open {
+        let key = (
+            UserId::new(row.user_id as u64),
+            ChannelId::new(row.channel_id as u64),
+        );
+
+        if in_voice.contains(&key) {
+            still_open.insert(key);
+            continue;
+        }
+
+        close_presence(&mut tx, row.id, row.last_seen.unwrap_or(now)).await?;
+    }
+
+    for (user, channel) in in_voice.difference(&still_open) {
+        let user_id = user.get() as i64;
+        let channel_id = channel.get() as i64;
+
+        sqlx::query!(
+            "INSERT INTO
+                voice_presence(server_id, channel_id, user_id, joined_at, last_seen)
+            VALUES
+                (?1, ?2, ?3, ?4, ?4);",
+            guild_id,
+            channel_id,
+            user_id,
+            now
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+// Ends a stay, crediting the time it overlapped with everyone still in the channel.
+// Stays that already ended were credited when they closed so they're skipped
+async fn close_presence(tx: &mut Transaction<'_, Sqlite>, id: i64, now: i64) -> sqlx::Result<()> {
+    sqlx::query!(
+        "INSERT INTO
+            vc_copresence(server_id, user_a, user_b, seconds)
+        SELECT
+            me.server_id,
+            MIN(me.user_id, other.user_id),
+            MAX(me.user_id, other.user_id),
+            ?1 - MAX(me.joined_at, other.joined_at)
+        FROM
+            voice_presence me
+            JOIN voice_presence other ON other.server_id = me.server_id
+            AND other.channel_id = me.channel_id
+            AND other.user_id != me.user_id
+            AND other.left_at IS NULL
+            AND other.joined_at < ?1
+        WHERE
+            me.id = ?2
+            AND me.left_at IS NULL
+        ON CONFLICT(server_id, user_a, user_b) DO UPDATE
+        SET
+            seconds = seconds + excluded.seconds;",
+        now,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    sqlx::query!(
+        "UPDATE
+            voice_presence
+        SET
+            left_at = ?
+        WHERE
+            id = ?;",
+        now,
+        id
+    )
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
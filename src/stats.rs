@@ -1,53 +1,400 @@
-use std::{
-    collections::HashMap,
-    time::{SystemTime, UNIX_EPOCH},
-};
+use std::collections::HashMap;
 
-use anyhow::Context;
+use anyhow::Context as _;
+use chrono::{DateTime, TimeDelta, Utc};
 use serenity::{
-    all::{ChannelId, UserId},
+    all::{ChannelId, ChannelType, GuildId, Message, UserId},
     builder::{CreateEmbed, CreateEmbedFooter, CreateMessage, GetMessages},
     http::CacheHttp,
+    prelude::Context,
 };
+use sqlx::SqlitePool;
 
+use crate::messages::MessageSettings;
+use crate::streaks;
 use crate::utils;
+use crate::voice::{self, VoiceSettings};
+use crate::words;
+
+// Anything said fewer times than this is just a coincidence, not a phrase
+const MIN_PHRASE_USES: u32 = 3;
+
+#[derive(Debug, Default)]
+pub struct VoiceDayStats {
+    pub seconds_elapsed: f64,
+    // Some of the day's sessions were running while the bot was offline
+    pub partial: bool,
+    pub stream_seconds: f64,
+    pub video_seconds: f64,
+    // Most used voice channels, longest first
+    pub top_channels: Vec<(ChannelId, f64)>,
+    // Current and best streak of days with vc activity for the whole server
+    pub guild_streak: (i64, i64),
+    // Longest ongoing member streaks, longest first
+    pub top_streaks: Vec<(UserId, i64)>,
+}
+
+impl VoiceDayStats {
+    // Totals for a day from the db, plus whatever sessions that are still going added to it
+    pub async fn fetch(ctx: &Context, db: &SqlitePool, guild_id: GuildId, day: &str) -> Self {
+        let settings = VoiceSettings::fetch(db, guild_id).await;
+        let ongoing = voice::ongoing_day_totals(ctx, &settings, guild_id, day).await;
+        let server_id = guild_id.get() as i64;
 
-const DAY: i64 = 60 * 60 * 24;
+        let mut stats = sqlx::query!(
+            "SELECT
+                vc_seconds_elapsed,
+                vc_partial_sessions,
+                vc_stream_seconds,
+                vc_video_seconds
+            FROM
+                days
+            WHERE
+                server_id = ?
+                AND day = ?;",
+            server_id,
+            day
+        )
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .map_or_else(Self::default, |record| Self {
+            seconds_elapsed: record.vc_seconds_elapsed.unwrap_or(0.0),
+            partial: record.vc_partial_sessions > 0,
+            stream_seconds: record.vc_stream_seconds,
+            video_seconds: record.vc_video_seconds,
+            top_channels: Vec::new(),
+            guild_streak: (0, 0),
+            top_streaks: Vec::new(),
+        });
+
+        let mut channels: HashMap<ChannelId, f64> = sqlx::query!(
+            "SELECT
+                channel_id,
+                vc_seconds_elapsed
+            FROM
+                vc_channel_days
+            WHERE
+                server_id = ?
+                AND day = ?;",
+            server_id,
+            day
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+        .into_iter()
+        .map(|record| {
+            (
+                ChannelId::new(record.channel_id as u64),
+                record.vc_seconds_elapsed,
+            )
+        })
+        .collect();
+
+        for (channel_id, totals) in ongoing {
+            stats.seconds_elapsed += totals.seconds as f64;
+            stats.partial |= totals.partial_sessions > 0;
+            stats.stream_seconds += totals.stream_seconds as f64;
+            stats.video_seconds += totals.video_seconds as f64;
+            *channels.entry(channel_id).or_default() += totals.seconds as f64;
+        }
 
-const SKIPPED_WORDS: &[&str] = &[
-    "there", "by", "at", "and", "so", "if", "than", "but", "about", "in", "on", "the", "was",
-    "for", "that", "said", "a", "or", "of", "to", "there", "will", "be", "what", "get", "go",
-    "think", "just", "every", "are", "it", "were", "had", "i", "",
-];
+        stats.top_channels = channels.into_iter().collect();
+        stats.top_channels.sort_by(|a, b| b.1.total_cmp(&a.1));
+
+        let today = utils::day_number(utils::today(settings.timezone));
+
+        for record in sqlx::query!(
+            "SELECT
+                user_id,
+                current,
+                best,
+                last_day
+            FROM
+                vc_streaks
+            WHERE
+                server_id = ?;",
+            server_id
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default()
+        {
+            let current = utils::current_streak(record.current, record.last_day, today);
+
+            if record.user_id == streaks::GUILD_STREAK {
+                stats.guild_streak = (current, record.best);
+            } else if current > 1 {
+                stats
+                    .top_streaks
+                    .push((UserId::new(record.user_id as u64), current));
+            }
+        }
+
+        stats
+            .top_streaks
+            .sort_by_key(|streak| std::cmp::Reverse(streak.1));
+
+        stats
+    }
+}
 
 #[derive(Debug)]
-struct UserInfo {
-    username: String,
-    messages: u32,
+pub struct UserInfo {
+    pub user_id: UserId,
+    pub username: String,
+    pub messages: u32,
 }
 
-pub async fn send_message_stats(
+#[derive(Debug, Default)]
+pub struct MessageDayStats {
+    pub messages: u32,
+    // Most active first
+    pub users: Vec<UserInfo>,
+    // Most used first, skipped words already left out
+    pub words: Vec<(String, u32)>,
+    // Two and three word phrases used at least MIN_PHRASE_USES times, most used first
+    pub phrases: Vec<(String, u32)>,
+    // Channels by messages sent, busiest first
+    pub top_channels: Vec<(ChannelId, u32)>,
+}
+
+impl MessageDayStats {
+    // Stats from the message counters for the channels the report covers,
+    // None if nothing was counted that day
+    pub async fn fetch(
+        db: &SqlitePool,
+        guild_id: GuildId,
+        day: &str,
+        counts_channel: impl Fn(ChannelId) -> bool,
+        shows_word: impl Fn(&str) -> bool,
+    ) -> Option<Self> {
+        let server_id = guild_id.get() as i64;
+        let in_scope = |channel_id: i64| counts_channel(ChannelId::new(channel_id as u64));
+
+        let counts = sqlx::query!(
+            "SELECT
+                channel_id,
+                user_id,
+                username,
+                messages
+            FROM
+                message_counts
+            WHERE
+                day = ?
+                AND server_id = ?;",
+            day,
+            server_id
+        )
+        .fetch_all(db)
+        .await
+        .ok()?;
+
+        let words = sqlx::query!(
+            "SELECT
+                channel_id,
+                word,
+                uses
+            FROM
+                word_counts
+            WHERE
+                day = ?
+                AND server_id = ?;",
+            day,
+            server_id
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default();
+
+        let phrases = sqlx::query!(
+            "SELECT
+                channel_id,
+                phrase,
+                uses
+            FROM
+                phrase_counts
+            WHERE
+                day = ?
+                AND server_id = ?;",
+            day,
+            server_id
+        )
+        .fetch_all(db)
+        .await
+        .unwrap_or_default();
+
+        let mut stats = Self::default();
+        let mut users: HashMap<UserId, UserInfo> = HashMap::new();
+        let mut channels: HashMap<ChannelId, u32> = HashMap::new();
+        let mut word_counts: HashMap<String, u32> = HashMap::new();
+        let mut phrase_counts: HashMap<String, u32> = HashMap::new();
+
+        for record in counts
+            .into_iter()
+            .filter(|record| in_scope(record.channel_id))
+        {
+            let user_id = UserId::new(record.user_id as u64);
+            let messages = record.messages as u32;
+
+            users
+                .entry(user_id)
+                .or_insert(UserInfo {
+                    user_id,
+                    username: record.username,
+                    messages: 0,
+                })
+                .messages += messages;
+
+            *channels
+                .entry(ChannelId::new(record.channel_id as u64))
+                .or_insert(0) += messages;
+            stats.messages += messages;
+        }
+
+        if users.is_empty() {
+            return None;
+        }
+
+        for record in words
+            .into_iter()
+            .filter(|record| in_scope(record.channel_id))
+            .filter(|record| shows_word(&record.word))
+        {
+            *word_counts.entry(record.word).or_insert(0) += record.uses as u32;
+        }
+
+        // Stop words could've changed since the phrase was counted, and blacklisted words
+        // shouldn't sneak in through a phrase
+        for record in phrases
+            .into_iter()
+            .filter(|record| in_scope(record.channel_id))
+            .filter(|record| record.phrase.split(' ').all(&shows_word))
+        {
+            *phrase_counts.entry(record.phrase).or_insert(0) += record.uses as u32;
+        }
+
+        stats.set_counts(users, channels, word_counts, phrase_counts);
+
+        Some(stats)
+    }
+
+    // Pages back through each channel's history for the given window, for days from before
+    // messages were being counted. Channels we can't read are skipped
+    pub async fn scrape(
+        http: impl CacheHttp,
+        channels: &[ChannelId],
+        (start, end): (DateTime<Utc>, DateTime<Utc>),
+        elisions: bool,
+        shows_word: impl Fn(&str) -> bool,
+    ) -> Self {
+        let mut stats = Self::default();
+        let mut users: HashMap<UserId, UserInfo> = HashMap::new();
+        let mut channel_counts: HashMap<ChannelId, u32> = HashMap::new();
+        let mut word_counts: HashMap<String, u32> = HashMap::new();
+        let mut phrase_counts: HashMap<String, u32> = HashMap::new();
+
+        for &channel in channels {
+            let Ok(messages) = messages_between(&http, channel, start, end).await else {
+                continue;
+            };
+
+            for message in &messages {
+                users
+                    .entry(message.author.id)
+                    .or_insert(UserInfo {
+                        user_id: message.author.id,
+                        username: message.author.name.clone(),
+                        messages: 0,
+                    })
+                    .messages += 1;
+
+                let tokens = words::tokenize(&message.content, elisions);
+                let kept: Vec<&str> = tokens
+                    .iter()
+                    .map(String::as_str)
+                    .filter(|word| shows_word(word))
+                    .collect();
+
+                for word in &kept {
+                    *word_counts.entry(word.to_string()).or_insert(0) += 1;
+                }
+
+                for phrase in words::phrases(&kept) {
+                    *phrase_counts.entry(phrase).or_insert(0) += 1;
+                }
+            }
+
+            if !messages.is_empty() {
+                channel_counts.insert(channel, messages.len() as u32);
+            }
+
+            stats.messages += messages.len() as u32;
+        }
+
+        stats.set_counts(users, channel_counts, word_counts, phrase_counts);
+
+        stats
+    }
+
+    fn set_counts(
+        &mut self,
+        users: HashMap<UserId, UserInfo>,
+        channels: HashMap<ChannelId, u32>,
+        words: HashMap<String, u32>,
+        phrases: HashMap<String, u32>,
+    ) {
+        self.users = users.into_values().collect();
+        self.users.sort_by_key(|x| std::cmp::Reverse(x.messages));
+
+        self.top_channels = channels.into_iter().collect();
+        self.top_channels.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+        self.words = words.into_iter().collect();
+        self.words.sort_by_key(|x| std::cmp::Reverse(x.1));
+
+        self.phrases = phrases
+            .into_iter()
+            .filter(|(_, uses)| *uses >= MIN_PHRASE_USES)
+            .collect();
+        self.phrases.sort_by_key(|x| std::cmp::Reverse(x.1));
+    }
+}
+
+// Every text channel and active thread in the guild, for scraping in guild wide mode
+pub fn text_channels(ctx: &Context, guild_id: GuildId) -> Vec<ChannelId> {
+    let Some(guild) = ctx.cache.guild(guild_id) else {
+        return Vec::new();
+    };
+
+    guild
+        .channels
+        .values()
+        .filter(|channel| matches!(channel.kind, ChannelType::Text | ChannelType::News))
+        .chain(guild.threads.iter())
+        .map(|channel| channel.id)
+        .collect()
+}
+
+async fn messages_between(
     http: impl CacheHttp,
     channel: ChannelId,
-    vc_seconds_elapsed: Option<f64>,
-) -> anyhow::Result<()> {
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> anyhow::Result<Vec<Message>> {
     let mut messages = channel
         .messages(&http, GetMessages::new().limit(100))
         .await?;
 
-    let now = SystemTime::now()
-        .duration_since(UNIX_EPOCH)
-        .expect("Time has gone backwards")
-        .as_secs();
-
-    while (now as i64
-        - messages
-            .last()
-            .context("No messages")?
-            .timestamp
-            .unix_timestamp())
-    .abs()
-        <= DAY
+    // Newest first, so keep going until we're past the start of the window
+    while messages
+        .last()
+        .context("No messages")?
+        .timestamp
+        .unix_timestamp()
+        >= start.timestamp()
     {
         let new_messages = channel
             .messages(
@@ -65,46 +412,218 @@ pub async fn send_message_stats(
         messages.extend(new_messages);
     }
 
-    messages.retain(|message| i64::abs(now as i64 - message.timestamp.unix_timestamp()) <= DAY);
+    messages.retain(|message| {
+        let timestamp = message.timestamp.unix_timestamp();
+        timestamp >= start.timestamp() && timestamp < end.timestamp()
+    });
+
+    Ok(messages)
+}
+
+impl MessageDayStats {
+    // Keeps the day's message totals around after the report has been sent
+    pub async fn save(&self, db: &SqlitePool, guild_id: GuildId, day: &str) -> sqlx::Result<()> {
+        let server_id = guild_id.get() as i64;
+        let unique_chatters = self.users.len() as i64;
+
+        let mut tx = db.begin().await?;
 
-    let mut user_info: HashMap<UserId, UserInfo> = HashMap::new();
-    let mut word_counts: HashMap<String, u32> = HashMap::new();
+        sqlx::query!(
+            "INSERT
+                OR IGNORE INTO servers(server_id)
+            VALUES
+                (?);
+
+            INSERT INTO
+                days(day, server_id, messages_sent, unique_chatters)
+            VALUES
+                (?, ?, ?, ?) ON CONFLICT(day, server_id) DO
+            UPDATE
+            SET
+                messages_sent = excluded.messages_sent,
+                unique_chatters = excluded.unique_chatters;",
+            server_id,
+            day,
+            server_id,
+            self.messages,
+            unique_chatters
+        )
+        .execute(&mut *tx)
+        .await?;
 
-    for message in &messages {
-        user_info
-            .entry(message.author.id)
-            .or_insert(UserInfo {
-                username: message.author.name.clone(),
-                messages: 0,
-            })
-            .messages += 1;
+        for user in &self.users {
+            let user_id = user.user_id.get() as i64;
 
-        message
-            .content
-            .split_whitespace()
-            .filter(|word| !SKIPPED_WORDS.contains(word))
-            .for_each(|word| {
-                *word_counts.entry(word.to_lowercase()).or_insert(0) += 1;
-            });
+            sqlx::query!(
+                "INSERT INTO
+                    user_days(day, server_id, user_id, username, messages_sent)
+                VALUES
+                    (?, ?, ?, ?, ?) ON CONFLICT(day, server_id, user_id) DO
+                UPDATE
+                SET
+                    username = excluded.username,
+                    messages_sent = excluded.messages_sent;",
+                day,
+                server_id,
+                user_id,
+                user.username,
+                user.messages
+            )
+            .execute(&mut *tx)
+            .await?;
+        }
+
+        tx.commit().await
     }
+}
 
-    let mut word_counts: Vec<(String, u32)> = Vec::from_iter(word_counts.into_iter());
-    word_counts.sort_by_key(|x| x.1);
-    word_counts.reverse();
+// Sends the report for the day that just ended to every guild that's reached its report hour
+// and hasn't had it yet
+pub async fn send_daily_reports(ctx: &Context, db: &SqlitePool) {
+    let now = Utc::now();
+
+    let servers = sqlx::query!(
+        "SELECT
+            server_id,
+            daily_log_channel,
+            timezone,
+            report_hour,
+            counting_since,
+            last_daily_report
+        FROM
+            servers
+        WHERE
+            daily_log_channel IS NOT NULL;"
+    )
+    .fetch_all(db)
+    .await
+    .unwrap_or_default();
+
+    for row in servers {
+        let tz = row.timezone.and_then(|timezone| timezone.parse().ok());
+
+        if utils::local_hour(now, tz) < row.report_hour as u32 {
+            continue;
+        }
+
+        let Some(channel_id) = row.daily_log_channel else {
+            continue;
+        };
+
+        let today = utils::local_date(now, tz);
+        let yesterday = today.pred_opt().unwrap();
+        let day = utils::day_key(yesterday);
+
+        if row.last_daily_report.as_ref() == Some(&day) {
+            continue;
+        }
+
+        // Marked before sending so a report that fails isn't retried every check
+        if let Err(why) = sqlx::query!(
+            "UPDATE
+                servers
+            SET
+                last_daily_report = ?
+            WHERE
+                server_id = ?;",
+            day,
+            row.server_id
+        )
+        .execute(db)
+        .await
+        {
+            println!("Failed to mark daily report as sent: {why}");
+            continue;
+        }
+
+        // Guilds that just set up reports start with the next day instead of catching up
+        if row.last_daily_report.is_none() {
+            continue;
+        }
 
-    let mut info: Vec<UserInfo> = user_info.into_values().collect();
-    info.sort_by_key(|x| x.messages);
+        let end = utils::midnight(today, tz).unwrap_or(now);
+        let start = utils::midnight(yesterday, tz).unwrap_or(end - TimeDelta::days(1));
 
+        send_daily_report(
+            ctx,
+            db,
+            GuildId::new(row.server_id as u64),
+            ChannelId::new(channel_id as u64),
+            &day,
+            (start, end),
+            row.counting_since,
+        )
+        .await;
+    }
+}
+
+// Builds and sends the report for a finished day, saving its message totals along the way
+pub async fn send_daily_report(
+    ctx: &Context,
+    db: &SqlitePool,
+    guild_id: GuildId,
+    channel_id: ChannelId,
+    day: &str,
+    (start, end): (DateTime<Utc>, DateTime<Utc>),
+    counting_since: Option<i64>,
+) {
+    let vc_stats = VoiceDayStats::fetch(ctx, db, guild_id, day).await;
+    let settings = MessageSettings::fetch(db, guild_id)
+        .await
+        .unwrap_or_default();
+    let guild = ctx.cache.guild(guild_id).map(|guild| guild.clone());
+
+    let counts_channel = |channel| settings.reports_on(guild.as_ref(), Some(channel_id), channel);
+
+    // Fall back to history for days from before we were counting, the counters would only
+    // have part of them
+    let message_stats = if counting_since.is_some_and(|since| since <= start.timestamp()) {
+        MessageDayStats::fetch(db, guild_id, day, counts_channel, |word| {
+            settings.shows_word(word)
+        })
+        .await
+        .unwrap_or_default()
+    } else {
+        let channels: Vec<ChannelId> = if settings.guild_wide {
+            text_channels(ctx, guild_id)
+        } else {
+            vec![channel_id]
+        }
+        .into_iter()
+        .filter(|&channel| counts_channel(channel))
+        .collect();
+
+        MessageDayStats::scrape(ctx, &channels, (start, end), settings.elisions, |word| {
+            settings.shows_word(word)
+        })
+        .await
+    };
+
+    if let Err(why) = message_stats.save(db, guild_id, day).await {
+        println!("Failed to save daily message stats: {why}");
+    }
+
+    if let Err(why) = send_message_stats(ctx, channel_id, message_stats, vc_stats).await {
+        println!("Failed to send daily stats: {why}");
+    }
+}
+
+pub async fn send_message_stats(
+    http: impl CacheHttp,
+    channel: ChannelId,
+    message_stats: MessageDayStats,
+    vc_stats: VoiceDayStats,
+) -> anyhow::Result<()> {
     let mut embed = CreateEmbed::new()
         .title("Active members")
         .color(0xe190de)
         .footer(CreateEmbedFooter::new(format!(
             "Total messages: {} | Unique chatters: {}",
-            messages.len(),
-            info.len()
+            message_stats.messages,
+            message_stats.users.len()
         )));
 
-    for (i, user) in info.iter().rev().take(8).enumerate() {
+    for (i, user) in message_stats.users.iter().take(8).enumerate() {
         embed = embed.field(
             format!("#{} {}", i + 1, user.username),
             format!("{} messages", user.messages),
@@ -112,21 +631,125 @@ pub async fn send_message_stats(
         );
     }
 
-    let words: String = word_counts
-        .iter()
-        .take(8)
-        .map(|x| x.0.clone())
-        .collect::<Vec<String>>()
-        .join(", ");
+    // Discord rejects empty field values, which a day with no messages (or only links and
+    // emoji) would leave here
+    if !message_stats.words.is_empty() {
+        let words: String = message_stats
+            .words
+            .iter()
+            .take(8)
+            .map(|x| x.0.clone())
+            .collect::<Vec<String>>()
+            .join(", ");
+
+        embed = embed.field("Most used words", words, false);
+    }
+
+    if !message_stats.phrases.is_empty() {
+        embed = embed.field(
+            "Most used phrases",
+            message_stats
+                .phrases
+                .iter()
+                .take(5)
+                .map(|(phrase, uses)| format!("\"{}\" ({} times)", phrase, uses))
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
 
-    embed = embed.field("Most used words", words, false);
+    // Only worth showing when the report covers more than one channel
+    if message_stats.top_channels.len() > 1 {
+        embed = embed.field(
+            "Top channels",
+            message_stats
+                .top_channels
+                .iter()
+                .take(5)
+                .map(|(channel_id, messages)| {
+                    format!(
+                        "<#{}> - {}",
+                        channel_id,
+                        utils::unit("message", *messages as f32)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
 
     embed = embed.field(
-        "Total time in vc today",
-        utils::format_from_seconds(vc_seconds_elapsed.unwrap_or(0.0) as f32),
+        "Total time in vc yesterday",
+        utils::format_vc_time(vc_stats.seconds_elapsed as f32, vc_stats.partial),
         false,
     );
 
+    if !vc_stats.top_channels.is_empty() {
+        embed = embed.field(
+            "Top voice channels yesterday",
+            vc_stats
+                .top_channels
+                .iter()
+                .take(3)
+                .map(|(channel_id, seconds)| {
+                    format!(
+                        "<#{}> - {}",
+                        channel_id,
+                        utils::format_from_seconds(*seconds as f32)
+                    )
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
+
+    if vc_stats.guild_streak.0 > 0 {
+        embed = embed.field(
+            "VC streak",
+            format!(
+                "{} (best: {})",
+                utils::unit("day", vc_stats.guild_streak.0 as f32),
+                utils::unit("day", vc_stats.guild_streak.1 as f32)
+            ),
+            false,
+        );
+    }
+
+    if !vc_stats.top_streaks.is_empty() {
+        embed = embed.field(
+            "Longest member streaks",
+            vc_stats
+                .top_streaks
+                .iter()
+                .take(3)
+                .map(|(user_id, days)| {
+                    format!("<@{}> - {}", user_id, utils::unit("day", *days as f32))
+                })
+                .collect::<Vec<_>>()
+                .join("\n"),
+            false,
+        );
+    }
+
+    if vc_stats.stream_seconds > 0.0 {
+        embed = embed.field(
+            "Screen share yesterday",
+            utils::format_from_seconds(vc_stats.stream_seconds as f32),
+            true,
+        );
+    }
+
+    if vc_stats.video_seconds > 0.0 {
+        embed = embed.field(
+            "Camera yesterday",
+            utils::format_from_seconds(vc_stats.video_seconds as f32),
+            true,
+        );
+    }
+
     channel
         .send_message(&http, CreateMessage::new().add_embed(embed))
         .await?;
@@ -0,0 +1,255 @@
+use std::collections::{HashMap, HashSet};
+
+use serenity::all::{CreateMessage, GuildId, UserId};
+use serenity::prelude::Context;
+use sqlx::SqlitePool;
+
+use crate::utils;
+use crate::voice::{VoiceChannelState, VoiceSettings};
+
+// Streaks for the server as a whole are stored under this user id
+pub const GUILD_STREAK: i64 = 0;
+
+/// Marks a day as having vc activity for a member (or the whole server with [`GUILD_STREAK`]).
+///
+/// Returns the new best streak if this just beat a previous record set by a different streak.
+pub async fn record_activity(
+    db: &SqlitePool,
+    guild_id: GuildId,
+    user_id: i64,
+    day: i64,
+) -> sqlx::Result<Option<i64>> {
+    let guild_id = guild_id.get() as i64;
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "INSERT
+            OR IGNORE INTO servers(server_id)
+        VALUES
+            (?);
+
+        INSERT
+            OR IGNORE INTO vc_streaks(server_id, user_id)
+        VALUES
+            (?, ?);",
+        guild_id,
+        guild_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    let streak = sqlx::query!(
+        "SELECT
+            current,
+            current_start,
+            best,
+            best_start,
+            last_day
+        FROM
+            vc_streaks
+        WHERE
+            server_id = ?
+            AND user_id = ?;",
+        guild_id,
+        user_id
+    )
+    .fetch_one(&mut *tx)
+    .await?;
+
+    // Already counted, or an older day showing up late
+    if streak.last_day >= day {
+        return Ok(None);
+    }
+
+    let (current, current_start) = if streak.last_day == day - 1 {
+        (streak.current + 1, streak.current_start)
+    } else {
+        (1, day)
+    };
+
+    let (best, best_start) = if current > streak.best {
+        (current, current_start)
+    } else {
+        (streak.best, streak.best_start)
+    };
+
+    sqlx::query!(
+        "UPDATE
+            vc_streaks
+        SET
+            current = ?,
+            current_start = ?,
+            best = ?,
+            best_start = ?,
+            last_day = ?
+        WHERE
+            server_id = ?
+            AND user_id = ?;",
+        current,
+        current_start,
+        best,
+        best_start,
+        day,
+        guild_id,
+        user_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    // A one day "record" isn't worth announcing
+    let beat_record =
+        current > streak.best && streak.best > 1 && current_start != streak.best_start;
+
+    Ok(beat_record.then_some(best))
+}
+
+pub struct StreakRecord {
+    pub user: Option<UserId>,
+    pub best: i64,
+}
+
+// Records activity for a member and the server, returning any records that got beaten
+pub async fn record_member_activity(
+    db: &SqlitePool,
+    guild_id: GuildId,
+    user: UserId,
+    day: i64,
+) -> sqlx::Result<Vec<StreakRecord>> {
+    let mut records = Vec::new();
+
+    if let Some(best) = record_activity(db, guild_id, user.get() as i64, day).await? {
+        records.push(StreakRecord {
+            user: Some(user),
+            best,
+        });
+    }
+
+    if let Some(best) = record_activity(db, guild_id, GUILD_STREAK, day).await? {
+        records.push(StreakRecord { user: None, best });
+    }
+
+    Ok(records)
+}
+
+// Called for anyone who was in a tracked channel before or after a voice update
+pub async fn update_streaks(
+    ctx: &Context,
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    guild_id: GuildId,
+    user: UserId,
+) {
+    let records = match record_member_activity(
+        db,
+        guild_id,
+        user,
+        utils::day_number(utils::today(settings.timezone)),
+    )
+    .await
+    {
+        Ok(records) => records,
+        Err(why) => {
+            println!("Failed to update vc streaks: {why}");
+            return;
+        }
+    };
+
+    let Some(channel) = settings.logs_channel else {
+        return;
+    };
+
+    if !settings.announce_streak_records {
+        return;
+    }
+
+    for record in records {
+        let content = match record.user {
+            Some(user) => format!(
+                "🔥 <@{user}> just beat their vc streak record with {} days in a row!",
+                record.best
+            ),
+            None => format!(
+                "🔥 This server just beat its vc streak record with {} days in a row!",
+                record.best
+            ),
+        };
+
+        if let Err(why) = channel
+            .send_message(&ctx.http, CreateMessage::new().content(content))
+            .await
+        {
+            println!("Failed to send streak record message: {why}");
+        }
+    }
+}
+
+// Credits everyone still in a tracked channel with today, so staying in vc past midnight keeps
+// a streak going without needing a voice update. Ran every minute
+pub async fn credit_present_members(ctx: &Context, db: &SqlitePool) {
+    let present: HashMap<GuildId, HashSet<UserId>> = {
+        let data = ctx.data.read().await;
+        let channels = data.get::<VoiceChannelState>().unwrap();
+        let mut present: HashMap<GuildId, HashSet<UserId>> = HashMap::new();
+
+        // Restored sessions haven't been checked against the cache yet
+        for vc_data in channels
+            .values()
+            .filter(|vc_data| vc_data.offline_since.is_none())
+        {
+            present.entry(vc_data.guild).or_default().extend(
+                vc_data
+                    .members
+                    .iter()
+                    .filter(|(_, member)| member.present.is_active())
+                    .map(|(user, _)| *user),
+            );
+        }
+
+        present
+    };
+
+    for (guild_id, users) in present {
+        let server_id = guild_id.get() as i64;
+        let today = utils::day_number(utils::today(utils::fetch_timezone(db, guild_id).await));
+
+        let credited: HashSet<i64> = match sqlx::query!(
+            "SELECT
+                user_id
+            FROM
+                vc_streaks
+            WHERE
+                server_id = ?
+                AND last_day >= ?;",
+            server_id,
+            today
+        )
+        .fetch_all(db)
+        .await
+        {
+            Ok(rows) => rows.into_iter().map(|row| row.user_id).collect(),
+            Err(why) => {
+                println!("Failed to fetch vc streaks: {why}");
+                continue;
+            }
+        };
+
+        let uncredited: Vec<UserId> = users
+            .into_iter()
+            .filter(|user| !credited.contains(&(user.get() as i64)))
+            .collect();
+
+        if uncredited.is_empty() {
+            continue;
+        }
+
+        let settings = VoiceSettings::fetch(db, guild_id).await;
+
+        for user in uncredited {
+            update_streaks(ctx, db, &settings, guild_id, user).await;
+        }
+    }
+}
@@ -1,8 +1,116 @@
-use chrono::Local;
+use chrono::{DateTime, Datelike, Local, NaiveDate, TimeZone, Timelike, Utc};
+use chrono_tz::Tz;
+use serenity::all::GuildId;
+use sqlx::SqlitePool;
 
-pub fn get_timestamp() -> String {
+pub async fn fetch_timezone(db: &SqlitePool, guild_id: GuildId) -> Option<Tz> {
+    try_fetch_timezone(db, guild_id).await.ok().flatten()
+}
+
+pub async fn try_fetch_timezone(db: &SqlitePool, guild_id: GuildId) -> sqlx::Result<Option<Tz>> {
+    let guild_id = guild_id.get() as i64;
+
+    let record = sqlx::query!(
+        "SELECT
+            timezone
+        FROM
+            servers
+        WHERE
+            server_id = ?;",
+        guild_id
+    )
+    .fetch_optional(db)
+    .await?;
+
+    Ok(record
+        .and_then(|record| record.timezone)
+        .and_then(|timezone| timezone.parse().ok()))
+}
+
+// Guilds that haven't set a timezone (None) go by the host's local time
+pub fn local_date(time: DateTime<Utc>, tz: Option<Tz>) -> NaiveDate {
+    match tz {
+        Some(tz) => time.with_timezone(&tz).date_naive(),
+        None => time.with_timezone(&Local).date_naive(),
+    }
+}
+
+pub fn local_hour(time: DateTime<Utc>, tz: Option<Tz>) -> u32 {
+    match tz {
+        Some(tz) => time.with_timezone(&tz).hour(),
+        None => time.with_timezone(&Local).hour(),
+    }
+}
+
+pub fn today(tz: Option<Tz>) -> NaiveDate {
+    local_date(Utc::now(), tz)
+}
+
+// When a day starts in the guild's timezone
+pub fn midnight(date: NaiveDate, tz: Option<Tz>) -> Option<DateTime<Utc>> {
+    let midnight = date.and_hms_opt(0, 0, 0)?;
+
+    match tz {
+        Some(tz) => tz
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|midnight| midnight.with_timezone(&Utc)),
+        None => Local
+            .from_local_datetime(&midnight)
+            .earliest()
+            .map(|midnight| midnight.with_timezone(&Utc)),
+    }
+}
+
+// Streak days are stored as days since the common era
+pub fn day_number(date: NaiveDate) -> i64 {
+    date.num_days_from_ce() as i64
+}
+
+// A streak that wasn't continued yesterday or today is over
+pub fn current_streak(current: i64, last_day: i64, today: i64) -> i64 {
+    if last_day >= today - 1 {
+        current
+    } else {
+        0
+    }
+}
+
+pub fn get_timestamp(tz: Option<Tz>) -> String {
+    day_key(today(tz))
+}
+
+pub fn day_key(date: NaiveDate) -> String {
     // month-day-year, month and day are non-padded
-    Local::now().format("%-m-%-d-%Y").to_string()
+    date.format("%-m-%-d-%Y").to_string()
+}
+
+// Splits the time between start and end into seconds per day, cut at the guild's midnight
+pub fn split_by_day(
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+    tz: Option<Tz>,
+) -> Vec<(String, f32)> {
+    let mut days = Vec::new();
+    let mut from = start;
+
+    while from < end {
+        let date = local_date(from, tz);
+        let next_midnight = date
+            .succ_opt()
+            .and_then(|date| midnight(date, tz))
+            .filter(|midnight| *midnight > from)
+            .unwrap_or(end);
+        let to = next_midnight.min(end);
+
+        days.push((
+            day_key(date),
+            (to - from).num_milliseconds() as f32 / 1000.0,
+        ));
+        from = to;
+    }
+
+    days
 }
 
 pub fn format_from_seconds(seconds: f32) -> String {
@@ -27,7 +135,43 @@ pub fn format_from_seconds(seconds: f32) -> String {
     unit("second", seconds)
 }
 
-fn unit(unit: &str, num: f32) -> String {
+// Time for sessions we didn't see start or end is only a lower bound
+pub fn format_vc_time(seconds: f32, partial: bool) -> String {
+    if partial {
+        format!("at least {}", format_from_seconds(seconds))
+    } else {
+        format_from_seconds(seconds)
+    }
+}
+
+// Joins lines until they would go over `limit` characters (e.g. discord's embed field limit)
+pub fn truncate_lines(lines: impl ExactSizeIterator<Item = String>, limit: usize) -> String {
+    let total = lines.len();
+    let mut joined = String::new();
+
+    for (i, line) in lines.enumerate() {
+        let remaining = format!("\nand {} more", total - i);
+
+        if joined.len() + line.len() + 1 + remaining.len() > limit {
+            joined.push_str(if joined.is_empty() {
+                remaining.trim_start()
+            } else {
+                &remaining
+            });
+            break;
+        }
+
+        if !joined.is_empty() {
+            joined.push('\n');
+        }
+
+        joined.push_str(&line);
+    }
+
+    joined
+}
+
+pub fn unit(unit: &str, num: f32) -> String {
     let extension = if num != 1.0 { "s" } else { "" };
 
     if num.fract() == 0.0 {
@@ -36,3 +180,108 @@ fn unit(unit: &str, num: f32) -> String {
         format!("{:.1} {}{extension}", num, unit)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono_tz::{America, UTC};
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0)
+            .unwrap()
+    }
+
+    fn day(day: &str, hours: f32) -> (String, f32) {
+        (day.to_string(), hours * 3600.0)
+    }
+
+    #[test]
+    fn split_by_day_within_a_day() {
+        assert_eq!(
+            split_by_day(utc(2024, 5, 1, 10, 0), utc(2024, 5, 1, 12, 30), Some(UTC)),
+            vec![day("5-1-2024", 2.5)]
+        );
+    }
+
+    #[test]
+    fn split_by_day_across_midnight() {
+        assert_eq!(
+            split_by_day(utc(2024, 5, 1, 23, 0), utc(2024, 5, 2, 1, 30), Some(UTC)),
+            vec![day("5-1-2024", 1.0), day("5-2-2024", 1.5)]
+        );
+    }
+
+    #[test]
+    fn split_by_day_across_several_days() {
+        assert_eq!(
+            split_by_day(utc(2024, 12, 30, 18, 0), utc(2025, 1, 2, 6, 0), Some(UTC)),
+            vec![
+                day("12-30-2024", 6.0),
+                day("12-31-2024", 24.0),
+                day("1-1-2025", 24.0),
+                day("1-2-2025", 6.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_day_uses_the_guild_midnight() {
+        // 22:00 to 02:00 in New York (UTC-4 in summer)
+        assert_eq!(
+            split_by_day(
+                utc(2024, 7, 2, 2, 0),
+                utc(2024, 7, 2, 6, 0),
+                Some(America::New_York)
+            ),
+            vec![day("7-1-2024", 2.0), day("7-2-2024", 2.0)]
+        );
+    }
+
+    #[test]
+    fn split_by_day_across_dst_change() {
+        // Clocks went forward on March 10th, so that day only had 23 hours
+        assert_eq!(
+            split_by_day(
+                utc(2024, 3, 10, 4, 0),
+                utc(2024, 3, 11, 5, 0),
+                Some(America::New_York)
+            ),
+            vec![
+                day("3-9-2024", 1.0),
+                day("3-10-2024", 23.0),
+                day("3-11-2024", 1.0),
+            ]
+        );
+
+        // And back on November 3rd, giving it 25
+        assert_eq!(
+            split_by_day(
+                utc(2024, 11, 3, 3, 0),
+                utc(2024, 11, 4, 6, 0),
+                Some(America::New_York)
+            ),
+            vec![
+                day("11-2-2024", 1.0),
+                day("11-3-2024", 25.0),
+                day("11-4-2024", 1.0),
+            ]
+        );
+    }
+
+    #[test]
+    fn split_by_day_without_a_local_midnight() {
+        // Sao Paulo skipped from 00:00 straight to 01:00 on November 4th 2018
+        let date = NaiveDate::from_ymd_opt(2018, 11, 4).unwrap();
+        assert_eq!(midnight(date, Some(America::Sao_Paulo)), None);
+
+        // 22:00 on the 3rd to 03:00 on the 4th, with no midnight to cut at, it all lands on the 3rd
+        assert_eq!(
+            split_by_day(
+                utc(2018, 11, 4, 1, 0),
+                utc(2018, 11, 4, 5, 0),
+                Some(America::Sao_Paulo)
+            ),
+            vec![day("11-3-2018", 4.0)]
+        );
+    }
+}
@@ -0,0 +1,1439 @@
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use chrono_tz::Tz;
+use serenity::all::{ChannelId, ChannelType, Embed, Guild, GuildId, MessageId, UserId, VoiceState};
+use serenity::builder::{CreateEmbed, CreateEmbedFooter, CreateMessage, EditMessage};
+use serenity::prelude::*;
+use sqlx::SqlitePool;
+
+use crate::utils;
+
+// Discord won't take more embeds than this in one message
+const MAX_EMBEDS: usize = 10;
+// Or more text than this across all of them
+const MAX_EMBED_TEXT: usize = 6000;
+
+pub struct VoiceChannelState;
+
+impl TypeMapKey for VoiceChannelState {
+    type Value = HashMap<ChannelId, VoiceChannelData>;
+}
+
+#[derive(Debug, Clone)]
+pub struct VoiceChannelData {
+    pub guild: GuildId,
+    pub members: HashMap<UserId, VoiceMember>,
+    pub start: DateTime<Utc>,
+    // The session was already going when we first saw it (or ended while the bot was offline),
+    // so the real start/end time is unknown and the elapsed time is only a lower bound
+    pub partial: bool,
+    pub peak_members: usize,
+    // Everyone in the channel is deafened, so it's more or less idle
+    pub fully_deafened: Timer,
+    // Live "VC session in progress" message, if the guild has those turned on
+    pub message: Option<(ChannelId, MessageId)>,
+    pub stage: bool,
+    // Last topic the stage had while the session was going
+    pub topic: Option<String>,
+    // Set on sessions restored from the db until they're first synced, the last time the bot saw
+    // the session going before it went offline
+    pub offline_since: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone)]
+pub struct VoiceMember {
+    pub first_joined: DateTime<Utc>,
+    pub last_left: Option<DateTime<Utc>>,
+    pub present: Timer,
+    pub muted: Timer,
+    pub deafened: Timer,
+    pub streaming: Timer,
+    pub video: Timer,
+    pub speaking: Timer,
+    pub hand_raised: Timer,
+}
+
+impl VoiceMember {
+    fn joined(now: DateTime<Utc>) -> Self {
+        Self {
+            first_joined: now,
+            last_left: None,
+            present: Timer::started(now),
+            muted: Timer::default(),
+            deafened: Timer::default(),
+            streaming: Timer::default(),
+            video: Timer::default(),
+            speaking: Timer::default(),
+            hand_raised: Timer::default(),
+        }
+    }
+
+    fn update(&mut self, voice_state: Option<&VoiceState>, stage: bool, now: DateTime<Utc>) {
+        if self.present.is_active() && voice_state.is_none() {
+            self.last_left = Some(now);
+        }
+
+        self.present.set(voice_state.is_some(), now);
+        self.muted.set(
+            voice_state.is_some_and(|state| state.mute || state.self_mute),
+            now,
+        );
+        self.deafened.set(
+            voice_state.is_some_and(|state| state.deaf || state.self_deaf),
+            now,
+        );
+        self.streaming.set(
+            voice_state.is_some_and(|state| state.self_stream.unwrap_or(false)),
+            now,
+        );
+        self.video
+            .set(voice_state.is_some_and(|state| state.self_video), now);
+
+        // Everyone in a stage channel is suppressed unless they're a speaker
+        self.speaking.set(
+            stage && voice_state.is_some_and(|state| !state.suppress),
+            now,
+        );
+        self.hand_raised.set(
+            stage
+                && voice_state.is_some_and(|state| {
+                    state.suppress && state.request_to_speak_timestamp.is_some()
+                }),
+            now,
+        );
+    }
+}
+
+/// A session's contribution to one row of the days table
+#[derive(Debug, Clone, Default)]
+pub struct DayTotals {
+    pub seconds: f32,
+    pub partial_sessions: i64,
+    pub stream_seconds: f32,
+    pub video_seconds: f32,
+    pub deafened_seconds: f32,
+}
+
+impl DayTotals {
+    pub fn add(&mut self, other: &DayTotals) {
+        self.seconds += other.seconds;
+        self.partial_sessions += other.partial_sessions;
+        self.stream_seconds += other.stream_seconds;
+        self.video_seconds += other.video_seconds;
+        self.deafened_seconds += other.deafened_seconds;
+    }
+}
+
+// Picks one of a member's timers, e.g. to total up screen share time
+pub type MemberTimer = fn(&VoiceMember) -> &Timer;
+
+#[derive(Debug, Clone)]
+pub struct VoiceSettings {
+    pub exclude_deafened: bool,
+    pub ignore_bots: bool,
+    pub ignore_afk_channel: bool,
+    pub ignored_channels: HashSet<ChannelId>,
+    pub min_session_seconds: i64,
+    pub min_participants: i64,
+    pub count_short_sessions: bool,
+    pub live_session_messages: bool,
+    pub announce_streak_records: bool,
+    pub logs_channel: Option<ChannelId>,
+    pub timezone: Option<Tz>,
+}
+
+impl Default for VoiceSettings {
+    fn default() -> Self {
+        Self {
+            exclude_deafened: false,
+            ignore_bots: false,
+            ignore_afk_channel: true,
+            ignored_channels: HashSet::new(),
+            min_session_seconds: 0,
+            min_participants: 1,
+            count_short_sessions: true,
+            live_session_messages: false,
+            announce_streak_records: false,
+            logs_channel: None,
+            timezone: None,
+        }
+    }
+}
+
+impl VoiceSettings {
+    pub async fn fetch(db: &SqlitePool, guild_id: GuildId) -> Self {
+        let guild_id = guild_id.get() as i64;
+
+        let mut settings = sqlx::query!(
+            "SELECT
+                exclude_deafened,
+                ignore_bots,
+                ignore_afk_channel,
+                min_session_seconds,
+                min_participants,
+                count_short_sessions,
+                live_session_messages,
+                announce_streak_records
+            FROM
+                vc_settings
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_optional(db)
+        .await
+        .ok()
+        .flatten()
+        .map_or_else(Self::default, |record| Self {
+            exclude_deafened: record.exclude_deafened != 0,
+            ignore_bots: record.ignore_bots != 0,
+            ignore_afk_channel: record.ignore_afk_channel != 0,
+            ignored_channels: HashSet::new(),
+            min_session_seconds: record.min_session_seconds,
+            min_participants: record.min_participants,
+            count_short_sessions: record.count_short_sessions != 0,
+            live_session_messages: record.live_session_messages != 0,
+            announce_streak_records: record.announce_streak_records != 0,
+            logs_channel: None,
+            timezone: None,
+        });
+
+        if let Ok(Some(record)) = sqlx::query!(
+            "SELECT
+                vc_logs_channel,
+                timezone
+            FROM
+                servers
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_optional(db)
+        .await
+        {
+            settings.logs_channel = record
+                .vc_logs_channel
+                .map(|channel_id| ChannelId::new(channel_id as u64));
+            settings.timezone = record.timezone.and_then(|timezone| timezone.parse().ok());
+        }
+
+        if let Ok(ignored_channels) = sqlx::query!(
+            "SELECT
+                channel_id
+            FROM
+                vc_ignored_channels
+            WHERE
+                server_id = ?;",
+            guild_id
+        )
+        .fetch_all(db)
+        .await
+        {
+            settings.ignored_channels = ignored_channels
+                .into_iter()
+                .map(|record| ChannelId::new(record.channel_id as u64))
+                .collect();
+        }
+
+        settings
+    }
+
+    // Whether a session is long/big enough to be posted in the vc log channel
+    pub fn should_log(&self, vc_data: &VoiceChannelData, now: DateTime<Utc>) -> bool {
+        vc_data.seconds_elapsed(now) >= self.min_session_seconds as f32
+            && vc_data.members.len() as i64 >= self.min_participants
+    }
+
+    // The channel a voice state should be tracked under, if it should be tracked at all
+    pub fn tracked_channel(&self, guild: &Guild, voice_state: &VoiceState) -> Option<ChannelId> {
+        let channel_id = voice_state.channel_id?;
+
+        if self.ignored_channels.contains(&channel_id) {
+            return None;
+        }
+
+        if self.ignore_afk_channel
+            && guild
+                .afk_metadata
+                .as_ref()
+                .is_some_and(|afk| afk.afk_channel_id == channel_id)
+        {
+            return None;
+        }
+
+        if self.ignore_bots {
+            let is_bot = voice_state
+                .member
+                .as_ref()
+                .or_else(|| guild.members.get(&voice_state.user_id))
+                .is_some_and(|member| member.user.bot);
+
+            if is_bot {
+                return None;
+            }
+        }
+
+        Some(channel_id)
+    }
+}
+
+/// Adds up how long something has been active, across any number of on/off switches
+#[derive(Debug, Clone, Default)]
+pub struct Timer {
+    pub since: Option<DateTime<Utc>>,
+    pub seconds: f32,
+}
+
+impl Timer {
+    pub fn started(now: DateTime<Utc>) -> Self {
+        Self {
+            since: Some(now),
+            seconds: 0.0,
+        }
+    }
+
+    fn from_row(since: Option<i64>, seconds: f64) -> Self {
+        Self {
+            since: since.map(from_timestamp),
+            seconds: seconds as f32,
+        }
+    }
+
+    pub fn is_active(&self) -> bool {
+        self.since.is_some()
+    }
+
+    pub fn set(&mut self, active: bool, now: DateTime<Utc>) {
+        match (self.since, active) {
+            (None, true) => self.since = Some(now),
+            (Some(since), false) => {
+                self.seconds += seconds_between(since, now);
+                self.since = None;
+            }
+            _ => {}
+        }
+    }
+
+    pub fn total(&self, now: DateTime<Utc>) -> f32 {
+        self.seconds + self.since.map_or(0.0, |since| seconds_between(since, now))
+    }
+}
+
+fn seconds_between(from: DateTime<Utc>, to: DateTime<Utc>) -> f32 {
+    (to - from).num_milliseconds() as f32 / 1000.0
+}
+
+fn from_timestamp(timestamp: i64) -> DateTime<Utc> {
+    DateTime::from_timestamp(timestamp, 0).unwrap_or_else(Utc::now)
+}
+
+fn timestamp(time: Option<DateTime<Utc>>) -> Option<i64> {
+    time.map(|time| time.timestamp())
+}
+
+impl VoiceChannelData {
+    pub fn new(guild: GuildId, now: DateTime<Utc>) -> Self {
+        Self {
+            guild,
+            members: HashMap::new(),
+            start: now,
+            partial: false,
+            peak_members: 0,
+            fully_deafened: Timer::default(),
+            message: None,
+            stage: false,
+            topic: None,
+            offline_since: None,
+        }
+    }
+
+    pub fn seconds_elapsed(&self, now: DateTime<Utc>) -> f32 {
+        seconds_between(self.start, now)
+    }
+
+    pub fn present_members(&self) -> usize {
+        self.members
+            .values()
+            .filter(|member| member.present.is_active())
+            .count()
+    }
+
+    pub fn member_total(&self, now: DateTime<Utc>, timer: MemberTimer) -> f32 {
+        self.members
+            .values()
+            .map(|member| timer(member).total(now))
+            .sum()
+    }
+
+    // Time that counts towards the daily total
+    pub fn counted_seconds(&self, settings: &VoiceSettings, now: DateTime<Utc>) -> f32 {
+        if settings.exclude_deafened {
+            self.seconds_elapsed(now) - self.fully_deafened.total(now)
+        } else {
+            self.seconds_elapsed(now)
+        }
+    }
+
+    // Splits the session's time across the days it covered, so a session going past midnight
+    // counts towards both days. Member activity is split in the same proportions
+    pub fn totals_by_day(
+        &self,
+        settings: &VoiceSettings,
+        partial: bool,
+        now: DateTime<Utc>,
+    ) -> Vec<(String, DayTotals)> {
+        let seconds_elapsed = self.seconds_elapsed(now);
+        let counted_seconds = self.counted_seconds(settings, now);
+        let stream_seconds = self.member_total(now, |member| &member.streaming);
+        let video_seconds = self.member_total(now, |member| &member.video);
+        let deafened_seconds = self.member_total(now, |member| &member.deafened);
+
+        utils::split_by_day(self.start, now, settings.timezone)
+            .into_iter()
+            .map(|(day, seconds)| {
+                let share = if seconds_elapsed > 0.0 {
+                    seconds / seconds_elapsed
+                } else {
+                    0.0
+                };
+
+                (
+                    day,
+                    DayTotals {
+                        seconds: counted_seconds * share,
+                        partial_sessions: partial as i64,
+                        stream_seconds: stream_seconds * share,
+                        video_seconds: video_seconds * share,
+                        deafened_seconds: deafened_seconds * share,
+                    },
+                )
+            })
+            .collect()
+    }
+
+    fn present_user_ids(&self) -> HashSet<UserId> {
+        self.members
+            .iter()
+            .filter(|(_, member)| member.present.is_active())
+            .map(|(user, _)| *user)
+            .collect()
+    }
+
+    // Brings the session in line with the voice states of who is currently in the channel,
+    // returns whether anyone joined or left
+    fn update_members(
+        &mut self,
+        present: &HashMap<UserId, VoiceState>,
+        now: DateTime<Utc>,
+    ) -> bool {
+        let present_before = self.present_user_ids();
+
+        for (user, member) in self.members.iter_mut() {
+            member.update(present.get(user), self.stage, now);
+        }
+
+        for (user, voice_state) in present {
+            self.members
+                .entry(*user)
+                .or_insert_with(|| VoiceMember::joined(now))
+                .update(Some(voice_state), self.stage, now);
+        }
+
+        let present_members = self.present_members();
+
+        self.peak_members = self.peak_members.max(present_members);
+        self.fully_deafened.set(
+            present_members > 0
+                && self
+                    .members
+                    .values()
+                    .filter(|member| member.present.is_active())
+                    .all(|member| member.deafened.is_active()),
+            now,
+        );
+
+        present_before != self.present_user_ids()
+    }
+}
+
+// Sessions are mirrored into sqlite so a restart in the middle of a vc doesn't lose them
+pub async fn save_session(
+    db: &SqlitePool,
+    channel_id: ChannelId,
+    vc_data: &VoiceChannelData,
+) -> sqlx::Result<()> {
+    let channel_id = channel_id.get() as i64;
+    let guild_id = vc_data.guild.get() as i64;
+    let start = vc_data.start.timestamp();
+    let partial = vc_data.partial;
+    let peak_members = vc_data.peak_members as i64;
+    let fully_deafened_since = timestamp(vc_data.fully_deafened.since);
+    let message_channel_id = vc_data
+        .message
+        .map(|(channel_id, _)| channel_id.get() as i64);
+    let message_id = vc_data
+        .message
+        .map(|(_, message_id)| message_id.get() as i64);
+    let stage = vc_data.stage;
+    let last_seen = Utc::now().timestamp();
+
+    let mut tx = db.begin().await?;
+
+    sqlx::query!(
+        "INSERT INTO
+            vc_sessions(
+                channel_id,
+                server_id,
+                start,
+                partial,
+                peak_members,
+                fully_deafened_since,
+                fully_deafened_seconds,
+                message_channel_id,
+                message_id,
+                stage,
+                topic,
+                last_seen
+            )
+        VALUES
+            (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?) ON CONFLICT(channel_id) DO
+        UPDATE
+        SET
+            server_id = excluded.server_id,
+            start = excluded.start,
+            partial = excluded.partial,
+            peak_members = excluded.peak_members,
+            fully_deafened_since = excluded.fully_deafened_since,
+            fully_deafened_seconds = excluded.fully_deafened_seconds,
+            message_channel_id = excluded.message_channel_id,
+            message_id = excluded.message_id,
+            stage = excluded.stage,
+            topic = excluded.topic,
+            last_seen = excluded.last_seen;
+
+        DELETE FROM
+            vc_session_members
+        WHERE
+            channel_id = ?;",
+        channel_id,
+        guild_id,
+        start,
+        partial,
+        peak_members,
+        fully_deafened_since,
+        vc_data.fully_deafened.seconds,
+        message_channel_id,
+        message_id,
+        stage,
+        vc_data.topic,
+        last_seen,
+        channel_id
+    )
+    .execute(&mut *tx)
+    .await?;
+
+    for (user, member) in &vc_data.members {
+        let user_id = user.get() as i64;
+        let first_joined = member.first_joined.timestamp();
+        let last_left = timestamp(member.last_left);
+        let present_since = timestamp(member.present.since);
+        let muted_since = timestamp(member.muted.since);
+        let deafened_since = timestamp(member.deafened.since);
+        let streaming_since = timestamp(member.streaming.since);
+        let video_since = timestamp(member.video.since);
+        let speaking_since = timestamp(member.speaking.since);
+        let hand_raised_since = timestamp(member.hand_raised.since);
+
+        sqlx::query!(
+            "INSERT INTO
+                vc_session_members(
+                    channel_id,
+                    user_id,
+                    first_joined,
+                    last_left,
+                    present_since,
+                    seconds,
+                    muted_since,
+                    muted_seconds,
+                    deafened_since,
+                    deafened_seconds,
+                    streaming_since,
+                    streaming_seconds,
+                    video_since,
+                    video_seconds,
+                    speaking_since,
+                    speaking_seconds,
+                    hand_raised_since,
+                    hand_raised_seconds
+                )
+            VALUES
+                (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?);",
+            channel_id,
+            user_id,
+            first_joined,
+            last_left,
+            present_since,
+            member.present.seconds,
+            muted_since,
+            member.muted.seconds,
+            deafened_since,
+            member.deafened.seconds,
+            streaming_since,
+            member.streaming.seconds,
+            video_since,
+            member.video.seconds,
+            speaking_since,
+            member.speaking.seconds,
+            hand_raised_since,
+            member.hand_raised.seconds
+        )
+        .execute(&mut *tx)
+        .await?;
+    }
+
+    tx.commit().await
+}
+
+pub async fn delete_session(db: &SqlitePool, channel_id: ChannelId) -> sqlx::Result<()> {
+    let channel_id = channel_id.get() as i64;
+
+    sqlx::query!(
+        "DELETE FROM
+            vc_sessions
+        WHERE
+            channel_id = ?;",
+        channel_id
+    )
+    .execute(db)
+    .await
+    .map(|_| ())
+}
+
+pub async fn load_sessions(db: &SqlitePool) -> sqlx::Result<HashMap<ChannelId, VoiceChannelData>> {
+    let mut channels: HashMap<ChannelId, VoiceChannelData> = HashMap::new();
+
+    let sessions = sqlx::query!(
+        "SELECT
+            channel_id,
+            server_id,
+            start,
+            partial,
+            peak_members,
+            fully_deafened_since,
+            fully_deafened_seconds,
+            message_channel_id,
+            message_id,
+            stage,
+            topic,
+            last_seen
+        FROM
+            vc_sessions;"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for session in sessions {
+        channels.insert(
+            ChannelId::new(session.channel_id as u64),
+            VoiceChannelData {
+                guild: GuildId::new(session.server_id as u64),
+                members: HashMap::new(),
+                start: from_timestamp(session.start),
+                partial: session.partial != 0,
+                peak_members: session.peak_members as usize,
+                fully_deafened: Timer::from_row(
+                    session.fully_deafened_since,
+                    session.fully_deafened_seconds,
+                ),
+                message: session.message_channel_id.zip(session.message_id).map(
+                    |(channel_id, message_id)| {
+                        (
+                            ChannelId::new(channel_id as u64),
+                            MessageId::new(message_id as u64),
+                        )
+                    },
+                ),
+                stage: session.stage != 0,
+                topic: session.topic,
+                // Sessions saved before last_seen existed can only be closed when we're back
+                offline_since: Some(session.last_seen.map_or_else(Utc::now, from_timestamp)),
+            },
+        );
+    }
+
+    let members = sqlx::query!(
+        "SELECT
+            channel_id,
+            user_id,
+            first_joined,
+            last_left,
+            present_since,
+            seconds,
+            muted_since,
+            muted_seconds,
+            deafened_since,
+            deafened_seconds,
+            streaming_since,
+            streaming_seconds,
+            video_since,
+            video_seconds,
+            speaking_since,
+            speaking_seconds,
+            hand_raised_since,
+            hand_raised_seconds
+        FROM
+            vc_session_members;"
+    )
+    .fetch_all(db)
+    .await?;
+
+    for member in members {
+        if let Some(vc_data) = channels.get_mut(&ChannelId::new(member.channel_id as u64)) {
+            vc_data.members.insert(
+                UserId::new(member.user_id as u64),
+                VoiceMember {
+                    first_joined: member.first_joined.map_or(vc_data.start, from_timestamp),
+                    last_left: member.last_left.map(from_timestamp),
+                    present: Timer::from_row(member.present_since, member.seconds),
+                    muted: Timer::from_row(member.muted_since, member.muted_seconds),
+                    deafened: Timer::from_row(member.deafened_since, member.deafened_seconds),
+                    streaming: Timer::from_row(member.streaming_since, member.streaming_seconds),
+                    video: Timer::from_row(member.video_since, member.video_seconds),
+                    speaking: Timer::from_row(member.speaking_since, member.speaking_seconds),
+                    hand_raised: Timer::from_row(
+                        member.hand_raised_since,
+                        member.hand_raised_seconds,
+                    ),
+                },
+            );
+        }
+    }
+
+    Ok(channels)
+}
+
+/// What caused a sync, used to tell fresh sessions apart from ones we missed the start/end of
+#[derive(Debug, Clone, Copy)]
+pub enum SyncReason {
+    /// A member's voice state changed, with the tracked channel they're now in (if any)
+    VoiceUpdate(UserId, Option<ChannelId>),
+    /// Catching up with the cache after startup or a reconnect
+    Resync,
+}
+
+/// Reconciles tracked vc sessions with the voice states in the guild cache, starting sessions for
+/// newly occupied channels and logging the ones that emptied out.
+///
+/// A newly seen channel is only considered a fresh session if the member that triggered the sync
+/// is the only one in it, anything else was already going before we could see it (e.g. the bot
+/// just started) and is marked as partial.
+pub async fn sync_guild(
+    ctx: &Context,
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    guild_id: GuildId,
+    reason: SyncReason,
+) {
+    // Discord only gets talked to once the lock is released, so nothing else that needs the
+    // shared data is stuck waiting on requests or rate limits
+    let messages = {
+        let mut data = ctx.data.write().await;
+        let channels = data.get_mut::<VoiceChannelState>().unwrap();
+
+        update_sessions(ctx, db, settings, guild_id, reason, channels).await
+    };
+
+    send_session_messages(ctx, db, settings, messages).await;
+}
+
+// Messages a sync has to post, edit or delete
+#[derive(Default)]
+struct SessionMessages {
+    live: Vec<LiveUpdate>,
+    // Live messages of sessions that ended too short to be logged
+    deleted: Vec<(ChannelId, MessageId)>,
+    // Logs of the sessions that ended, replacing their live message if they had one
+    logs: Vec<(CreateEmbed, Option<(ChannelId, MessageId)>)>,
+    // The guild's total time in vc today, added to the logs
+    total: String,
+}
+
+// A live message to edit, or post if the session doesn't have one yet
+struct LiveUpdate {
+    channel_id: ChannelId,
+    // Matches a newly posted message back up with its session
+    start: DateTime<Utc>,
+    embed: CreateEmbed,
+    message: Option<(ChannelId, MessageId)>,
+}
+
+async fn update_sessions(
+    ctx: &Context,
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    guild_id: GuildId,
+    reason: SyncReason,
+    channels: &mut HashMap<ChannelId, VoiceChannelData>,
+) -> SessionMessages {
+    let mut messages = SessionMessages::default();
+    let Some(guild) = ctx.cache.guild(guild_id).map(|guild| guild.clone()) else {
+        return messages;
+    };
+
+    let now = Utc::now();
+    let mut present: HashMap<ChannelId, HashMap<UserId, VoiceState>> = HashMap::new();
+
+    for user_voice_state in guild.voice_states.values() {
+        if let Some(channel_id) = settings.tracked_channel(&guild, user_voice_state) {
+            present
+                .entry(channel_id)
+                .or_default()
+                .insert(user_voice_state.user_id, user_voice_state.clone());
+        }
+    }
+
+    for (channel_id, members) in &present {
+        if !channels.contains_key(channel_id) {
+            let mut vc_data = VoiceChannelData::new(guild.id, now);
+
+            vc_data.stage = guild
+                .channels
+                .get(channel_id)
+                .is_some_and(|channel| channel.kind == ChannelType::Stage);
+            vc_data.partial = !matches!(
+                reason,
+                SyncReason::VoiceUpdate(user, Some(joined_channel))
+                    if joined_channel == *channel_id
+                        && members.len() == 1
+                        && members.contains_key(&user)
+            );
+
+            channels.insert(*channel_id, vc_data);
+        }
+    }
+
+    let no_members = HashMap::new();
+    let mut dead_channels: HashMap<ChannelId, (VoiceChannelData, DateTime<Utc>)> = HashMap::new();
+
+    for (channel_id, vc_data) in channels.iter_mut() {
+        if vc_data.guild != guild.id {
+            continue;
+        }
+
+        let members = present.get(channel_id).unwrap_or(&no_members);
+        let offline_since = vc_data.offline_since.take();
+        let mut members_changed = false;
+
+        // Anyone who isn't here anymore left at some point while we were offline, the last time
+        // we saw the session is as close as we can get to when
+        if let Some(offline_since) = offline_since {
+            let stayed: HashMap<UserId, VoiceState> = members
+                .iter()
+                .filter(|(user, _)| {
+                    vc_data
+                        .members
+                        .get(user)
+                        .is_some_and(|member| member.present.is_active())
+                })
+                .map(|(user, voice_state)| (*user, voice_state.clone()))
+                .collect();
+
+            members_changed = vc_data.update_members(&stayed, offline_since);
+        }
+
+        members_changed |= vc_data.update_members(members, now);
+
+        if let Some(stage_instance) = guild
+            .stage_instances
+            .iter()
+            .find(|stage_instance| stage_instance.channel_id == *channel_id)
+        {
+            vc_data.topic = Some(stage_instance.topic.clone());
+        }
+
+        if vc_data.present_members() == 0 {
+            let mut ended = vc_data.clone();
+
+            // A restored session that's already empty ended while we were offline, and after a
+            // reconnect we can't know when a session ended either
+            ended.partial |= offline_since.is_some() || matches!(reason, SyncReason::Resync);
+            dead_channels.insert(*channel_id, (ended, offline_since.unwrap_or(now)));
+            continue;
+        }
+
+        if members_changed && settings.live_session_messages {
+            messages.live.push(LiveUpdate {
+                channel_id: *channel_id,
+                start: vc_data.start,
+                embed: live_session_embed(*channel_id, vc_data, now),
+                message: vc_data.message,
+            });
+        }
+
+        if let Err(why) = save_session(db, *channel_id, vc_data).await {
+            println!("Failed to save vc session: {why}");
+        }
+    }
+
+    if dead_channels.is_empty() {
+        return messages;
+    }
+
+    let timestamp = utils::get_timestamp(settings.timezone);
+    let mut days: HashMap<String, DayTotals> =
+        HashMap::from([(timestamp.clone(), DayTotals::default())]);
+    let mut channel_days: HashMap<(String, ChannelId), f32> = HashMap::new();
+    let mut embeds: Vec<(CreateEmbed, Option<(ChannelId, MessageId)>)> = Vec::new();
+
+    for (channel_id, (vc_data, ended_at)) in dead_channels {
+        let partial = vc_data.partial;
+        let logged = settings.should_log(&vc_data, ended_at);
+
+        if logged {
+            embeds.push((
+                session_ended_embed(channel_id, &vc_data, partial, ended_at),
+                vc_data.message,
+            ));
+        } else if let Some(message) = vc_data.message {
+            messages.deleted.push(message);
+        }
+
+        if logged || settings.count_short_sessions {
+            for (day, totals) in vc_data.totals_by_day(settings, partial, ended_at) {
+                *channel_days.entry((day.clone(), channel_id)).or_default() += totals.seconds;
+                days.entry(day).or_default().add(&totals);
+            }
+        }
+
+        channels.remove(&channel_id);
+
+        if let Err(why) = delete_session(db, channel_id).await {
+            println!("Failed to delete vc session: {why}");
+        }
+    }
+
+    let guild_id = guild.id.get() as i64;
+    let mut update = Ok(());
+
+    for (day, totals) in days {
+        let result = sqlx::query!(
+            "INSERT
+                OR IGNORE INTO servers(server_id)
+            VALUES
+                (?);
+
+            INSERT
+                OR IGNORE INTO days(day, server_id)
+            VALUES
+                (?, ?);
+
+            UPDATE
+                days
+            SET
+                vc_seconds_elapsed = vc_seconds_elapsed + ?,
+                vc_partial_sessions = vc_partial_sessions + ?,
+                vc_stream_seconds = vc_stream_seconds + ?,
+                vc_video_seconds = vc_video_seconds + ?,
+                vc_deafened_seconds = vc_deafened_seconds + ?
+            WHERE
+                server_id = ?
+                AND day = ?;",
+            guild_id,
+            day,
+            guild_id,
+            totals.seconds,
+            totals.partial_sessions,
+            totals.stream_seconds,
+            totals.video_seconds,
+            totals.deafened_seconds,
+            guild_id,
+            day
+        )
+        .execute(db)
+        .await;
+
+        update = update.and(result.map(|_| ()));
+    }
+
+    for ((day, channel_id), seconds) in channel_days {
+        let channel_id = channel_id.get() as i64;
+
+        let result = sqlx::query!(
+            "INSERT INTO
+                vc_channel_days(day, server_id, channel_id, vc_seconds_elapsed)
+            VALUES
+                (?, ?, ?, ?) ON CONFLICT(day, server_id, channel_id) DO
+            UPDATE
+            SET
+                vc_seconds_elapsed = vc_seconds_elapsed + excluded.vc_seconds_elapsed;",
+            day,
+            guild_id,
+            channel_id,
+            seconds
+        )
+        .execute(db)
+        .await;
+
+        update = update.and(result.map(|_| ()));
+    }
+
+    let vc_seconds_elapsed = sqlx::query!(
+        "SELECT
+            vc_seconds_elapsed,
+            vc_partial_sessions
+        FROM
+            days
+        WHERE
+            server_id = ?
+            AND day = ?;",
+        guild_id,
+        timestamp
+    )
+    .fetch_one(db)
+    .await;
+
+    if let Ok(record) = update.and(vc_seconds_elapsed) {
+        messages.total = utils::format_vc_time(
+            record.vc_seconds_elapsed.unwrap() as f32,
+            record.vc_partial_sessions > 0,
+        );
+        messages.logs = embeds;
+    }
+
+    messages
+}
+
+async fn send_session_messages(
+    ctx: &Context,
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    messages: SessionMessages,
+) {
+    for update in messages.live {
+        let Some((message_channel, message_id)) = update.message else {
+            post_live_message(ctx, db, settings, update).await;
+            continue;
+        };
+
+        if let Err(why) = message_channel
+            .edit_message(
+                &ctx.http,
+                message_id,
+                EditMessage::new().embed(update.embed),
+            )
+            .await
+        {
+            println!("Failed to update live vc session message: {why}");
+        }
+    }
+
+    for (message_channel, message_id) in messages.deleted {
+        if let Err(why) = message_channel.delete_message(&ctx.http, message_id).await {
+            println!("Failed to delete live vc session message: {why}");
+        }
+    }
+
+    let total = messages.total;
+    let mut new_embeds = Vec::new();
+
+    for (embed, message) in messages.logs {
+        // Live sessions turn their message into the final log instead of posting a new one
+        let Some((message_channel, message_id)) = message else {
+            new_embeds.push(embed);
+            continue;
+        };
+
+        let embed = embed.field("Total time in vc today", &total, false);
+
+        if let Err(why) = message_channel
+            .edit_message(&ctx.http, message_id, EditMessage::new().embed(embed))
+            .await
+        {
+            println!("Failed to send vc session log: {why}");
+        }
+    }
+
+    let Some(logs_channel) = settings.logs_channel else {
+        return;
+    };
+
+    // Sessions that ended together go out as one message, with the daily total on the last embed
+    let total_length = "Total time in vc today".chars().count() + total.chars().count();
+    let mut batches: Vec<Vec<CreateEmbed>> = Vec::new();
+    let mut batch_length = 0;
+
+    for embed in new_embeds {
+        let length = embed_length(&embed);
+
+        match batches.last_mut() {
+            Some(batch)
+                if batch.len() < MAX_EMBEDS
+                    && batch_length + length + total_length <= MAX_EMBED_TEXT =>
+            {
+                batch.push(embed);
+                batch_length += length;
+            }
+            _ => {
+                batches.push(vec![embed]);
+                batch_length = length;
+            }
+        }
+    }
+
+    for mut batch in batches {
+        if let Some(last) = batch.pop() {
+            batch.push(last.field("Total time in vc today", &total, false));
+        }
+
+        if let Err(why) = logs_channel
+            .send_message(&ctx.http, CreateMessage::new().embeds(batch))
+            .await
+        {
+            println!("Failed to send vc session log: {why}");
+        }
+    }
+}
+
+// The text discord counts towards an embed's length limit
+fn embed_length(embed: &CreateEmbed) -> usize {
+    let Ok(embed) = serenity::json::to_value(embed).and_then(serenity::json::from_value::<Embed>)
+    else {
+        return MAX_EMBED_TEXT;
+    };
+
+    embed.title.map_or(0, |title| title.chars().count())
+        + embed
+            .description
+            .map_or(0, |description| description.chars().count())
+        + embed
+            .fields
+            .iter()
+            .map(|field| field.name.chars().count() + field.value.chars().count())
+            .sum::<usize>()
+        + embed.footer.map_or(0, |footer| footer.text.chars().count())
+        + embed.author.map_or(0, |author| author.name.chars().count())
+}
+
+// Posts a session's first live message and hands it to the session, unless the session ended
+// or got a message from another sync while this one was being sent
+async fn post_live_message(
+    ctx: &Context,
+    db: &SqlitePool,
+    settings: &VoiceSettings,
+    update: LiveUpdate,
+) {
+    let Some(logs_channel) = settings.logs_channel else {
+        return;
+    };
+
+    let message = match logs_channel
+        .send_message(&ctx.http, CreateMessage::new().add_embed(update.embed))
+        .await
+    {
+        Ok(message) => message,
+        Err(why) => {
+            println!("Failed to update live vc session message: {why}");
+            return;
+        }
+    };
+
+    {
+        let mut data = ctx.data.write().await;
+        let channels = data.get_mut::<VoiceChannelState>().unwrap();
+
+        if let Some(vc_data) = channels
+            .get_mut(&update.channel_id)
+            .filter(|vc_data| vc_data.start == update.start && vc_data.message.is_none())
+        {
+            vc_data.message = Some((logs_channel, message.id));
+
+            if let Err(why) = save_session(db, update.channel_id, vc_data).await {
+                println!("Failed to save vc session: {why}");
+            }
+
+            return;
+        }
+    }
+
+    if let Err(why) = message.delete(&ctx.http).await {
+        println!("Failed to delete live vc session message: {why}");
+    }
+}
+
+// What each of the guild's sessions that are still going has added to a day so far
+pub async fn ongoing_day_totals(
+    ctx: &Context,
+    settings: &VoiceSettings,
+    guild_id: GuildId,
+    day: &str,
+) -> HashMap<ChannelId, DayTotals> {
+    let data = ctx.data.read().await;
+    let channels = data.get::<VoiceChannelState>().unwrap();
+    let now = Utc::now();
+    let mut ongoing: HashMap<ChannelId, DayTotals> = HashMap::new();
+
+    for (channel_id, vc_data) in channels
+        .iter()
+        .filter(|(_, vc_data)| vc_data.guild == guild_id)
+    {
+        for (_, totals) in vc_data
+            .totals_by_day(settings, vc_data.partial, now)
+            .iter()
+            .filter(|(session_day, _)| session_day == day)
+        {
+            ongoing.entry(*channel_id).or_default().add(totals);
+        }
+    }
+
+    ongoing
+}
+
+// Keeps last_seen current for the sessions that are still going and the stays in them,
+// ran every minute
+pub async fn mark_sessions_seen(ctx: &Context, db: &SqlitePool) {
+    let channel_ids: Vec<i64> = {
+        let data = ctx.data.read().await;
+        let channels = data.get::<VoiceChannelState>().unwrap();
+
+        channels
+            .iter()
+            .filter(|(_, vc_data)| vc_data.offline_since.is_none())
+            .map(|(channel_id, _)| channel_id.get() as i64)
+            .collect()
+    };
+
+    if channel_ids.is_empty() {
+        return;
+    }
+
+    let channel_ids = serenity::json::to_string(&channel_ids).unwrap_or_default();
+    let now = Utc::now().timestamp();
+
+    if let Err(why) = sqlx::query!(
+        "UPDATE
+            vc_sessions
+        SET
+            last_seen = ?
+        WHERE
+            channel_id IN (
+                SELECT
+                    value
+                FROM
+                    json_each(?)
+            );
+
+        UPDATE
+            voice_presence
+        SET
+            last_seen = ?
+        WHERE
+            left_at IS NULL
+            AND channel_id IN (
+                SELECT
+                    value
+                FROM
+                    json_each(?)
+            );",
+        now,
+        channel_ids,
+        now,
+        channel_ids
+    )
+    .execute(db)
+    .await
+    {
+        println!("Failed to update vc session last seen times: {why}");
+    }
+}
+
+// Keeps the elapsed time on live session messages fresh, ran every minute
+pub async fn refresh_live_messages(ctx: &Context) {
+    let now = Utc::now();
+
+    // Build the embeds up front so the lock isn't held while we wait on discord
+    let edits: Vec<(ChannelId, MessageId, CreateEmbed)> = {
+        let data = ctx.data.read().await;
+        let channels = data.get::<VoiceChannelState>().unwrap();
+
+        channels
+            .iter()
+            .filter_map(|(channel_id, vc_data)| {
+                let (message_channel, message_id) = vc_data.message?;
+
+                Some((
+                    message_channel,
+                    message_id,
+                    live_session_embed(*channel_id, vc_data, now),
+                ))
+            })
+            .collect()
+    };
+
+    for (message_channel, message_id, embed) in edits {
+        if let Err(why) = message_channel
+            .edit_message(&ctx.http, message_id, EditMessage::new().embed(embed))
+            .await
+        {
+            println!("Failed to refresh live vc session message: {why}");
+        }
+    }
+}
+
+fn live_session_embed(
+    channel_id: ChannelId,
+    vc_data: &VoiceChannelData,
+    now: DateTime<Utc>,
+) -> CreateEmbed {
+    let mut members: Vec<(&UserId, &VoiceMember)> = vc_data
+        .members
+        .iter()
+        .filter(|(_, member)| member.present.is_active())
+        .collect();
+    members.sort_by(|a, b| b.1.present.total(now).total_cmp(&a.1.present.total(now)));
+
+    let in_channel = utils::truncate_lines(
+        members.iter().map(|(user, member)| {
+            format!(
+                "<@{}> - {}",
+                user,
+                utils::format_from_seconds(member.present.total(now))
+            )
+        }),
+        1024,
+    );
+
+    let mut embed = CreateEmbed::new()
+        .title(if vc_data.stage {
+            "Stage session in progress"
+        } else {
+            "VC session in progress"
+        })
+        .color(0xe190de)
+        .field("Channel", format!("<#{}>", channel_id), false);
+
+    if let Some(topic) = &vc_data.topic {
+        embed = embed.field("Topic", topic, false);
+    }
+
+    embed
+        .field("In the channel", in_channel, false)
+        .field("Peak members", vc_data.peak_members.to_string(), true)
+        .field(
+            "Time elapsed",
+            utils::format_vc_time(vc_data.seconds_elapsed(now), vc_data.partial),
+            true,
+        )
+        .field(
+            "Started",
+            format!("<t:{}:R>", vc_data.start.timestamp()),
+            true,
+        )
+}
+
+const ACTIVITIES: [(&str, MemberTimer); 6] = [
+    ("On stage", |member| &member.speaking),
+    ("Hand raised", |member| &member.hand_raised),
+    ("Screen share", |member| &member.streaming),
+    ("Camera", |member| &member.video),
+    ("Muted", |member| &member.muted),
+    ("Deafened", |member| &member.deafened),
+];
+
+// e.g. " (screen share 20 minutes, deafened 5 minutes)"
+fn member_activity(member: &VoiceMember, now: DateTime<Utc>) -> String {
+    let activity: Vec<String> = ACTIVITIES
+        .iter()
+        .map(|(name, timer)| (name, timer(member).total(now)))
+        .filter(|(_, seconds)| *seconds > 0.0)
+        .map(|(name, seconds)| {
+            format!(
+                "{} {}",
+                name.to_lowercase(),
+                utils::format_from_seconds(seconds)
+            )
+        })
+        .collect();
+
+    if activity.is_empty() {
+        String::new()
+    } else {
+        format!(" ({})", activity.join(", "))
+    }
+}
+
+fn session_ended_embed(
+    channel_id: ChannelId,
+    vc_data: &VoiceChannelData,
+    partial: bool,
+    now: DateTime<Utc>,
+) -> CreateEmbed {
+    let mut members: Vec<(&UserId, &VoiceMember)> = vc_data.members.iter().collect();
+    members.sort_by(|a, b| b.1.present.total(now).total_cmp(&a.1.present.total(now)));
+
+    let participants = utils::truncate_lines(
+        members.iter().map(|(user, member)| {
+            format!(
+                "<@{}> - {}{}",
+                user,
+                utils::format_from_seconds(member.present.total(now)),
+                member_activity(member, now)
+            )
+        }),
+        1024,
+    );
+
+    let mut embed = CreateEmbed::new()
+        .title(if vc_data.stage {
+            "Stage session ended"
+        } else {
+            "VC session ended"
+        })
+        .color(0xe190de)
+        .field("Channel", format!("<#{}>", channel_id), false);
+
+    if let Some(topic) = &vc_data.topic {
+        embed = embed.field("Topic", topic, false);
+    }
+
+    embed = embed.field("Participants", participants, false);
+
+    if let Some((user, _)) = members.iter().min_by_key(|(_, member)| member.first_joined) {
+        embed = embed.field("First to join", format!("<@{}>", user), true);
+    }
+
+    if let Some((user, _)) = members.iter().max_by_key(|(_, member)| member.last_left) {
+        embed = embed.field("Last to leave", format!("<@{}>", user), true);
+    }
+
+    embed = embed.field("Peak members", vc_data.peak_members.to_string(), true);
+
+    for (name, timer) in ACTIVITIES {
+        let seconds = vc_data.member_total(now, timer);
+
+        if seconds > 0.0 {
+            embed = embed.field(name, utils::format_from_seconds(seconds), true);
+        }
+    }
+
+    if vc_data.stage {
+        let listening = vc_data.member_total(now, |member| &member.present)
+            - vc_data.member_total(now, |member| &member.speaking);
+
+        embed = embed.field("In audience", utils::format_from_seconds(listening), true);
+    }
+
+    embed = embed.field(
+        "Time elapsed",
+        utils::format_vc_time(vc_data.seconds_elapsed(now), partial),
+        false,
+    );
+
+    if partial {
+        embed = embed.footer(CreateEmbedFooter::new(
+            "This session was running while the bot was offline, so its exact length is unknown",
+        ));
+    }
+
+    embed
+}
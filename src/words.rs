@@ -0,0 +1,235 @@
+// Built-in stop words (common words that say nothing about what people were talking about),
+// one word per line. Guilds pick which of these apply with /message-settings
+pub const LANGUAGES: &[(&str, &str, &str)] = &[
+    ("en", "English", include_str!("stop_words/en.txt")),
+    ("es", "Spanish", include_str!("stop_words/es.txt")),
+    ("de", "German", include_str!("stop_words/de.txt")),
+    ("fr", "French", include_str!("stop_words/fr.txt")),
+    ("pt", "Portuguese", include_str!("stop_words/pt.txt")),
+    ("it", "Italian", include_str!("stop_words/it.txt")),
+    ("nl", "Dutch", include_str!("stop_words/nl.txt")),
+];
+
+pub fn language_name(code: &str) -> Option<&'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(language, _, _)| *language == code)
+        .map(|(_, name, _)| *name)
+}
+
+pub fn stop_words(code: &str) -> impl Iterator<Item = &'static str> {
+    LANGUAGES
+        .iter()
+        .find(|(language, _, _)| *language == code)
+        .map_or("", |(_, _, words)| *words)
+        .lines()
+        .map(str::trim)
+        .filter(|word| !word.is_empty())
+}
+
+// French and Italian articles and pronouns that get elided onto the next word, like l'homme or
+// dell'anno. The stop word lists only have the full forms, so these are cut off before counting
+const ELIDED_PREFIXES: &[&str] = &[
+    "l'", "d'", "j'", "c'", "qu'", "n'", "s'", "m'", "t'", "lorsqu'", "puisqu'", "jusqu'", "un'",
+    "dell'", "all'", "dall'", "nell'", "sull'", "coll'", "quell'", "quest'", "nessun'",
+];
+
+// Only French and Italian elide articles, elsewhere "c'mon" and "all's" are words of their own
+pub fn elides(language: &str) -> bool {
+    matches!(language, "fr" | "it")
+}
+
+// Splits a message into lowercase words, leaving out code, urls, mentions and custom emoji.
+// Apostrophes and hyphens inside a word are kept so "don't" and "e-girl" stay one word,
+// apart from elisions (when asked for) where only the word after the apostrophe is kept
+pub fn tokenize(content: &str, elisions: bool) -> Vec<String> {
+    let text = strip_markup(&strip_code(&content.replace('’', "'")));
+
+    text.split_whitespace()
+        .filter(|word| !word.contains("://") && !word.starts_with("www."))
+        .flat_map(|word| word.split(|c: char| !(c.is_alphanumeric() || c == '\'' || c == '-')))
+        .map(|word| word.trim_matches(|c| c == '\'' || c == '-'))
+        .map(|word| if elisions { strip_elision(word) } else { word })
+        .filter(|word| word.chars().any(char::is_alphabetic))
+        .map(str::to_lowercase)
+        .collect()
+}
+
+fn strip_elision(word: &str) -> &str {
+    ELIDED_PREFIXES
+        .iter()
+        .find(|prefix| {
+            word.get(..prefix.len())
+                .is_some_and(|start| start.eq_ignore_ascii_case(prefix))
+        })
+        .map_or(word, |prefix| &word[prefix.len()..])
+}
+
+// Every run of two and three words, meant for a message's words with stop words taken out.
+// Runs of the same word over and over are spam more than a phrase so they're skipped
+pub fn phrases(words: &[&str]) -> Vec<String> {
+    (2..=3)
+        .flat_map(|len| words.windows(len))
+        .filter(|phrase| phrase.iter().any(|word| *word != phrase[0]))
+        .map(|phrase| phrase.join(" "))
+        .collect()
+}
+
+// Drops ``` blocks and then `inline` code, an unclosed fence takes the rest of the message
+fn strip_code(content: &str) -> String {
+    let without_blocks = content
+        .split("```")
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .join(" ");
+
+    without_blocks
+        .split('`')
+        .step_by(2)
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Drops <@user>, <#channel>, <:emoji:id>, <t:timestamp>, </command:id> and <https://links>
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+
+    while let Some(start) = rest.find('<') {
+        out.push_str(&rest[..start]);
+        let tag = &rest[start..];
+
+        match tag.find('>') {
+            Some(end) if is_markup(&tag[1..end]) => {
+                out.push(' ');
+                rest = &tag[end + 1..];
+            }
+            _ => {
+                out.push('<');
+                rest = &tag[1..];
+            }
+        }
+    }
+
+    out.push_str(rest);
+    out
+}
+
+fn is_markup(inner: &str) -> bool {
+    !inner.is_empty()
+        && !inner.contains(char::is_whitespace)
+        && (inner.starts_with(['@', '#', ':', '/'])
+            || inner.starts_with("a:")
+            || inner.starts_with("t:")
+            || inner.contains("://"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tokenize_lowercases_and_drops_punctuation() {
+        assert_eq!(
+            tokenize(
+                "Hello, WORLD!! It's a \"test\"... (really) - e-girl?",
+                false
+            ),
+            vec!["hello", "world", "it's", "a", "test", "really", "e-girl"]
+        );
+    }
+
+    #[test]
+    fn tokenize_normalises_apostrophes() {
+        assert_eq!(
+            tokenize("Don’t 'quote' me", false),
+            vec!["don't", "quote", "me"]
+        );
+    }
+
+    #[test]
+    fn tokenize_skips_numbers() {
+        assert_eq!(tokenize("gg 2024 100% 3am", false), vec!["gg", "3am"]);
+    }
+
+    #[test]
+    fn tokenize_strips_elisions() {
+        assert_eq!(
+            tokenize("C'est l'homme qu'il a vu, j'ai dit d'accord", true),
+            vec!["est", "homme", "il", "a", "vu", "ai", "dit", "accord"]
+        );
+        assert_eq!(
+            tokenize("Dell'anno, all'inizio c'è un'amica nell'aula", true),
+            vec!["anno", "inizio", "è", "amica", "aula"]
+        );
+        assert_eq!(tokenize("l' don't", true), vec!["l", "don't"]);
+    }
+
+    #[test]
+    fn tokenize_keeps_elisions_when_not_asked() {
+        assert_eq!(
+            tokenize("c'mon all's fine, t'was l'homme", false),
+            vec!["c'mon", "all's", "fine", "t'was", "l'homme"]
+        );
+    }
+
+    #[test]
+    fn tokenize_drops_mentions() {
+        assert_eq!(
+            tokenize("hi <@123> and <@!456> in <#789> with <@&1011>", false),
+            vec!["hi", "and", "in", "with"]
+        );
+    }
+
+    #[test]
+    fn tokenize_drops_custom_emoji() {
+        assert_eq!(
+            tokenize("nice <:pog:123> <a:dance:456>", false),
+            vec!["nice"]
+        );
+    }
+
+    #[test]
+    fn tokenize_drops_links() {
+        assert_eq!(
+            tokenize(
+                "see <https://example.com/page> or https://example.com and www.example.com",
+                false
+            ),
+            vec!["see", "or", "and"]
+        );
+    }
+
+    #[test]
+    fn strip_markup_leaves_hearts_alone() {
+        assert_eq!(strip_markup("i <3 this > that"), "i <3 this > that");
+        assert_eq!(
+            tokenize("i <3 this > that", false),
+            vec!["i", "this", "that"]
+        );
+    }
+
+    #[test]
+    fn strip_markup_keeps_other_angle_brackets() {
+        assert_eq!(strip_markup("a < b > c"), "a < b > c");
+        assert_eq!(strip_markup("<t:1700000000:R> ago"), "  ago");
+        assert_eq!(strip_markup("use </vc-stats:123>"), "use  ");
+    }
+
+    #[test]
+    fn strip_code_drops_blocks_and_inline_code() {
+        assert_eq!(
+            tokenize("look ```rust\nfn main() {}\n``` at `this` here", false),
+            vec!["look", "at", "here"]
+        );
+    }
+
+    #[test]
+    fn strip_code_unclosed_fence_takes_the_rest() {
+        assert_eq!(
+            tokenize("before ```let x = 1; never closed", false),
+            vec!["before"]
+        );
+        assert_eq!(tokenize("before `unclosed inline", false), vec!["before"]);
+    }
+}